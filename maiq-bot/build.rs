@@ -7,6 +7,7 @@ use pulldown_cmark::Parser;
 fn main() {
   println!("cargo:rerun-if-changed=replies");
   println!("cargo:rerun-if-changed=../.changes");
+  println!("cargo::rustc-check-cfg=cfg(profile, values(\"release\"))");
 
   build_info_build::build_script();
   for path in walkdir::WalkDir::new("replies")
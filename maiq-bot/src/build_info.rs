@@ -1,6 +1,6 @@
 use std::sync::OnceLock;
 
-use maiq_parser_next::utils::time::*;
+use maiq_time::*;
 
 build_info::build_info!(fn info);
 
@@ -22,10 +22,7 @@ pub fn build_info() -> &'static str {
       o = info.optimization_level,
       branch = branch.as_deref().unwrap_or("unknown"),
       commit = commit.as_deref().unwrap_or("unknown").split_at(7).0,
-      deployed_time = info
-        .timestamp
-        .with_timezone(&FixedOffset::east_opt(3 * 3600).unwrap())
-        .format("%d.%m.%Y %H:%m:%S")
+      deployed_time = timezone().from_utc(&info.timestamp).format("%d.%m.%Y %H:%m:%S")
     );
     ver
   })
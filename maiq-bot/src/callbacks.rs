@@ -1,5 +1,4 @@
 use anyhow::anyhow;
-use maiq_parser_next::parser::GROUP_NAMES;
 use teloxide::payloads::EditMessageTextSetters;
 use teloxide::requests::Requester;
 use teloxide::types::CallbackQuery;
@@ -14,12 +13,15 @@ use crate::handler::Handler;
 use crate::make_callbacks;
 use crate::markup;
 use crate::reply;
+use crate::start_link;
 
 make_callbacks! {
   Test(arg: i32) => test,
   SetMyGroups => show_my_groups,
   ShowConfig => show_config,
   SetGroup(name: String) => set_group,
+  SetMyCampuses => show_my_campuses,
+  SetCampus(name: String) => set_campus,
   GetStartLink => get_start_link,
   ToggleNotifications => toggle_notifications,
   ChangelogPage(page: usize) => show_changelog,
@@ -30,17 +32,32 @@ make_callbacks! {
 impl Handler {
   async fn get_my_groups(&self) -> InlineKeyboardMarkup {
     let user = self.user().await;
-    let into_button = |group: &&str| {
+    let into_button = |group: &str| {
       let name = if user.config().has_group(group) { format!("✅ {}", group) } else { group.to_string() };
       Callback::SetGroup { name: group.to_string() }.with_text(name).into()
     };
 
-    let buttons = GROUP_NAMES
-      .iter()
-      .step_by(3)
-      .zip(GROUP_NAMES.iter().skip(1).step_by(3).map(into_button))
-      .zip(GROUP_NAMES.iter().skip(2).step_by(3).map(into_button))
-      .map(|((group, btn1), btn2)| [into_button(group), btn1, btn2]);
+    let parser = self.parser.read().await;
+    let buttons = parser.groups().chunks(3).map(|row| {
+      row
+        .iter()
+        .map(|group| into_button(group))
+        .collect::<Vec<InlineKeyboardButton>>()
+    });
+
+    markup!(buttons)
+  }
+
+  async fn get_my_campuses(&self) -> InlineKeyboardMarkup {
+    let user = self.user().await;
+    let parser = self.parser.read().await;
+    let buttons = parser.campuses().into_iter().map(|campus| {
+      let name =
+        if user.config().campuses().iter().any(|c| c == campus) { format!("✅ {}", campus) } else { campus.to_string() };
+      [Callback::SetCampus { name: campus.to_string() }
+        .with_text(name)
+        .into()]
+    });
 
     markup!(buttons)
   }
@@ -64,18 +81,13 @@ impl Callbacks for Handler {
 
   async fn get_start_link(&self) -> Result<()> {
     let me = self.get_me().await?;
-    let mut link = String::new();
-    for idx in self
-      .user()
-      .await
-      .config()
-      .groups()
-      .iter()
-      .filter_map(|group| GROUP_NAMES.iter().position(|g| g == group))
-    {
-      link.push('g');
-      link.push_str(&idx.to_string());
-    }
+    let Some(link) = start_link::encode(self.user().await.config().groups()) else {
+      self.answer().await?;
+      self
+        .reply(reply!("start_link_too_many.md", max = start_link::MAX_GROUPS))
+        .await?;
+      return Ok(());
+    };
 
     let link = format!("https://t.me/{me}?start={link}", me = me.username.as_ref().unwrap(), link = link);
     self.answer().await?;
@@ -103,6 +115,28 @@ impl Callbacks for Handler {
     self.show_my_groups().await
   }
 
+  async fn show_my_campuses(&self) -> Result<()> {
+    let markup = self
+      .get_my_campuses()
+      .await
+      .append_row([Callback::ShowConfig.with_text("OK").into()]);
+    self
+      .edit(reply!(const "set_campuses.md"))
+      .reply_markup(markup)
+      .await?;
+    Ok(())
+  }
+
+  async fn set_campus(&self, name: String) -> Result<()> {
+    let mut user = self.user().await;
+    match user.config().campuses().contains(&name) {
+      true => user.config_mut().remove_campus(name, &self.pool).await?,
+      false => user.config_mut().add_campus(name, &self.pool).await?,
+    }
+    drop(user);
+    self.show_my_campuses().await
+  }
+
   async fn show_config(&self) -> Result<()> {
     self
       .edit(reply!(const "config.md"))
@@ -5,17 +5,19 @@ use crate::handler::Handler;
 use crate::make_commands;
 use crate::markup;
 use crate::reply;
+use crate::start_link;
 
 use anyhow::Result;
 
 use maiq_db::models::User;
-use maiq_parser_next::parser::GROUP_NAMES;
+use maiq_parser_next::prelude::*;
 use teloxide::payloads::SendMessageSetters;
 use teloxide::requests::Requester;
+use teloxide::utils::html;
 
 make_commands! {
   pub: {
-    Start[desc: "Стартовая команда", args: (group_indexes: String)] => start,
+    Start[desc: "Стартовая команда", args: (payload: String)] => start,
     Today[desc: "Сегодня"] => today,
     Next[desc: "Завтра"] => next,
     About[desc: "Информация"] => about,
@@ -25,22 +27,22 @@ make_commands! {
   },
   dev: {
     UserList => userlist,
+    Report => report,
+    Refresh => refresh,
+    Pause => pause,
+    Resume => resume,
     TestErr => test_err
   }
 }
 
 impl Commands for Handler {
-  async fn start(&self, group_indexes: String) -> Result<()> {
+  async fn start(&self, payload: String) -> Result<()> {
     let username = self.message.from().map(|u| u.full_name()).unwrap_or_default();
     self
       .reply(reply!("start.md", greeting = random_greeting(), username = username))
       .await?;
 
-    let groups = group_indexes
-      .split('g')
-      .map(|s| s.parse().map(|idx: usize| GROUP_NAMES.get(idx)))
-      .filter_map(|s| s.ok().flatten().map(|s| s.to_string()))
-      .collect::<Vec<String>>();
+    let groups = start_link::decode(&payload, self.parser.read().await.groups());
 
     if !groups.is_empty() {
       let mut user = self.user().await;
@@ -73,8 +75,8 @@ impl Commands for Handler {
   }
 
   async fn today(&self) -> Result<()> {
-    if let Some(snapshot) = self.parser.read().await.latest_today() {
-      self.reply_snapshot(snapshot).await?;
+    if let Some(snapshot) = self.snapshot_for_day(SourceDay::Today).await {
+      self.reply_snapshot(&snapshot).await?;
     } else {
       self.reply(reply!(const "err/no_timetable.md")).await?;
     }
@@ -82,8 +84,8 @@ impl Commands for Handler {
   }
 
   async fn next(&self) -> Result<()> {
-    if let Some(snapshot) = self.parser.read().await.latest_next() {
-      self.reply_snapshot(snapshot).await?;
+    if let Some(snapshot) = self.snapshot_for_day(SourceDay::Next).await {
+      self.reply_snapshot(&snapshot).await?;
     } else {
       self.reply(reply!(const "err/no_timetable.md")).await?;
     }
@@ -141,6 +143,52 @@ impl DeveloperCommands for Handler {
     Ok(())
   }
 
+  async fn report(&self) -> Result<()> {
+    let parser = self.parser.read().await;
+    let mut reply = String::new();
+    for source in parser.sources() {
+      let name = match parser.latest(source).and_then(|snapshot| snapshot.agent()) {
+        Some(agent) => format!("{} ({})", source, agent),
+        None => source.to_string(),
+      };
+      let report = parser.latest_report(source);
+      match report {
+        Some(report) if !report.is_empty() => {
+          reply.push_str(&format!("<b>{}</b>: {} замечаний\n", name, report.len()));
+          for entry in report.entries() {
+            reply.push_str(&format!("· {}\n", html::escape(&entry.to_string())));
+          }
+        }
+        Some(_) => reply.push_str(&format!("<b>{}</b>: без замечаний\n", name)),
+        None => reply.push_str(&format!("<b>{}</b>: нет расписания\n", name)),
+      }
+      if let Some(table) = report.and_then(|report| report.table()) {
+        reply.push_str(&format!("· {}\n", table));
+      }
+    }
+
+    self.reply(reply).await?;
+    Ok(())
+  }
+
+  async fn refresh(&self) -> Result<()> {
+    self.control.refresh_now();
+    self.reply("Обновляю расписание").await?;
+    Ok(())
+  }
+
+  async fn pause(&self) -> Result<()> {
+    self.control.pause();
+    self.reply("Парсер на паузе. /resume, чтобы продолжить").await?;
+    Ok(())
+  }
+
+  async fn resume(&self) -> Result<()> {
+    self.control.resume();
+    self.reply("Парсер снова работает").await?;
+    Ok(())
+  }
+
   async fn test_err(&self) -> Result<()> {
     Err(anyhow::anyhow!("Test error"))
   }
@@ -1,7 +1,7 @@
 use std::fmt::Display;
 
 use maiq_parser_next::prelude::*;
-use maiq_parser_next::utils::time::*;
+use maiq_time::*;
 
 pub struct FormatSnapshot<'a>(&'a Snapshot, FormatGroup<'a>);
 pub struct FormatGroup<'a>(pub &'a Group);
@@ -72,17 +72,24 @@ impl<'a> Display for FormatWeekday<'a> {
 
 impl<'a> Display for FormatDate<'a> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    let now = DateTime::now_date().date_naive();
     let date = self.0.format("%d.%m.%Y");
-    match self.0.date_naive().signed_duration_since(now).num_days() {
-      0 => write!(f, "сегодня, {}", date),
-      1 => write!(f, "завтра, {}", date),
-      2 => write!(f, "послезавтра, {}", date),
-      _ => write!(f, "{}", date),
+    match relative_day(self.0.date_naive(), DateTime::now().date_naive()) {
+      Some(day) => write!(f, "{}, {}", day, date),
+      None => write!(f, "{}", date),
     }
   }
 }
 
+/// Даты считаются в часовом поясе из [`timezone`], так что "сегодня" сменяется в местную полночь
+fn relative_day(date: NaiveDate, today: NaiveDate) -> Option<&'static str> {
+  match date.signed_duration_since(today).num_days() {
+    0 => Some("сегодня"),
+    1 => Some("завтра"),
+    2 => Some("послезавтра"),
+    _ => None,
+  }
+}
+
 const EMOJIES: [&str; 21] =
   ["🥭", "🥩", "🥝", "🌵", "🥞", "🧀", "🍖", "🍌", "🍍", "🥓", "🧃", "🍒", "🍓", "🍇", "🥕", "🐷", "🍺", "🍪", "🍁", "🍉", "🍋"];
 
@@ -95,3 +102,19 @@ pub fn random_greeting<'a>() -> &'a str {
 fn random_emoji<'a>() -> &'a str {
   EMOJIES[fastrand::usize(0..EMOJIES.len())]
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  #[case("2023-10-17T20:59:00", Some("завтра"))]
+  #[case("2023-10-17T21:00:00", Some("сегодня"))]
+  #[case("2023-10-15T21:00:00", Some("послезавтра"))]
+  #[case("2023-10-18T21:00:00", None)]
+  fn relative_day_at_midnight(#[case] utc: &str, #[case] expected: Option<&str>) {
+    let tz = "Europe/Moscow".parse::<Timezone>().unwrap();
+    let today = tz.from_naive_utc(&utc.parse().unwrap()).date_naive();
+    assert_eq!(relative_day(NaiveDate::from_ymd_opt(2023, 10, 18).unwrap(), today), expected);
+  }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use maiq_db::models::*;
 use maiq_parser_next::prelude::*;
+use maiq_time::*;
 use teloxide::payloads::EditMessageText;
 use teloxide::prelude::*;
 
@@ -27,18 +28,33 @@ pub struct Handler {
   pub user: Arc<Mutex<User>>,
   pub message: Arc<Message>,
   pub parser: SnapshotParser,
+  pub control: ParserHandle,
   pub pool: Arc<Pool>,
   caller: Option<teloxide::types::User>,
   callback_id: Option<String>,
 }
 
 impl Handler {
-  pub async fn with_message(bot: Bot, message: Message, parser: SnapshotParser, pool: Arc<Pool>) -> Option<Arc<Mutex<Self>>> {
+  pub async fn with_message(
+    bot: Bot,
+    message: Message,
+    parser: SnapshotParser,
+    control: ParserHandle,
+    pool: Arc<Pool>,
+  ) -> Option<Arc<Mutex<Self>>> {
     let caller = message.from().cloned();
     match User::get_by_id_or_create(message.chat.id.0, &pool).await {
       Ok(user) => {
-        let handler =
-          Self { bot, message: Arc::new(message), parser, user: Arc::new(Mutex::new(user)), pool, caller, callback_id: None };
+        let handler = Self {
+          bot,
+          message: Arc::new(message),
+          parser,
+          control,
+          user: Arc::new(Mutex::new(user)),
+          pool,
+          caller,
+          callback_id: None,
+        };
         let name = if handler.message.chat.is_private() {
           handler.caller_name()
         } else {
@@ -62,6 +78,7 @@ impl Handler {
     bot: Bot,
     query: CallbackQuery,
     parser: SnapshotParser,
+    control: ParserHandle,
     pool: Arc<Pool>,
   ) -> Option<Arc<Mutex<Self>>> {
     let message = match query.message {
@@ -78,6 +95,7 @@ impl Handler {
           bot,
           message: Arc::new(message),
           parser,
+          control,
           user: Arc::new(Mutex::new(user)),
           caller: Some(query.from),
           pool,
@@ -136,6 +154,38 @@ impl Handler {
     Ok(())
   }
 
+  /// Снапшот на сегодня или на ближайший следующий день, на который выложено расписание
+  pub async fn snapshot_for_day(&self, day: SourceDay) -> Option<Snapshot> {
+    let date = {
+      let parser = self.parser.read().await;
+      let today = parser.clock().today();
+      match day {
+        SourceDay::Today => today,
+        SourceDay::Next => parser.dates().find(|date| *date > today)?,
+      }
+    };
+    self.snapshot_for(date).await
+  }
+
+  /// Снапшот на дату из корпусов пользователя: первый, где есть его группы, или просто первый
+  pub async fn snapshot_for(&self, date: NaiveDate) -> Option<Snapshot> {
+    let parser = self.parser.read().await;
+    let user = self.user().await;
+    let config = user.config();
+    let snapshots = parser
+      .campuses()
+      .into_iter()
+      .filter(|campus| config.has_campus(campus))
+      .filter_map(|campus| parser.campus_snapshot_for(campus, date))
+      .collect::<Vec<&Snapshot>>();
+
+    snapshots
+      .iter()
+      .find(|snapshot| config.groups().iter().any(|group| snapshot.group(group).is_some()))
+      .or(snapshots.first())
+      .map(|snapshot| (*snapshot).clone())
+  }
+
   pub async fn answer(&self) -> Result<()> {
     if let Some(ref callback_id) = self.callback_id {
       self.answer_callback_query(callback_id).await?;
@@ -173,11 +223,12 @@ impl Handler {
       [Callback::GetStartLink.with_text("Получить стартовую ссылку").into()],
       [Callback::ToggleNotifications.with_text(toggle_text).into()],
       [Callback::SetMyGroups.with_text("Настроить группы").into()],
+      [Callback::SetMyCampuses.with_text("Выбрать корпус").into()],
       [Callback::Close.with_text("Закрыть").into()]
     ])
   }
 
-  pub async fn user(&self) -> MutexGuard<User> {
+  pub async fn user(&self) -> MutexGuard<'_, User> {
     self.user.lock().await
   }
 
@@ -5,13 +5,20 @@ mod commands;
 mod format;
 mod handler;
 mod parser;
+mod start_link;
 
 use anyhow::Result;
 use std::sync::Arc;
 use teloxide::utils::command::BotCommands;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
 use tokio::sync::RwLock;
 
+use maiq_db::models::SnapshotEntry;
+use maiq_parser_next::parser::selection::TableSelection;
 use maiq_parser_next::prelude::*;
+use maiq_time::set_timezone;
+use maiq_time::timezone;
 use parser::start_parser_service;
 use teloxide::dptree::deps;
 use teloxide::prelude::*;
@@ -27,21 +34,40 @@ mod macros;
 #[macro_use]
 extern crate log;
 
+#[cfg(test)]
+#[macro_use]
+extern crate rstest;
+
 use crate::callbacks::filter_callback;
 use crate::callbacks::Callback;
 use crate::commands::Command;
 use crate::commands::DeveloperCommand;
 
+/// Агент по умолчанию. Какой агент разберёт страницу, решает [`maiq_parser_next::parser::registry::AgentRegistry`]:
+/// с `LAYOUT_PATH` к нему добавляется агент с раскладкой из конфига
 pub type SnapshotParserImpl = SnapshotParser4;
 pub type SnapshotParser = Arc<RwLock<maiq_parser_next::prelude::SnapshotParser<SnapshotParserImpl>>>;
 
 pub const DEVELOPER_ID: u64 = 949248728;
 
+/// Корпуса по умолчанию, если не задана переменная `CAMPUSES`
+pub const DEFAULT_CAMPUSES: &str = "4korp";
+
 pub trait Caller {
   fn caller(&self) -> Option<&teloxide::types::User>;
   fn caller_name(&self) -> String;
 }
 
+/// Часовой пояс из `TIMEZONE`: IANA-имя или смещение, по умолчанию UTC+3
+pub fn setup_timezone() -> Result<()> {
+  match std::env::var("TIMEZONE") {
+    Ok(raw) => set_timezone(raw.parse().map_err(anyhow::Error::msg)?),
+    Err(_) => warn!(target: "setup", "env-var TIMEZONE not set; using {}", timezone()),
+  }
+  info!(target: "setup", "timezone: {}", timezone());
+  Ok(())
+}
+
 pub async fn setup_bot() -> Result<Bot> {
   let bot = Bot::from_env();
   let me = bot.get_me().await?;
@@ -53,26 +79,93 @@ pub async fn setup_bot() -> Result<Bot> {
   Ok(bot)
 }
 
-pub fn setup_parser() -> Result<SnapshotParser> {
-  let parser = SnapshotParserBuilder::new()
-    .with_today_url("https://rsp.chemk.org/4korp/today.htm")
-    .unwrap()
-    .with_next_url("https://rsp.chemk.org/4korp/tomorrow.htm")
-    .unwrap()
-    .build()?;
-  Ok(Arc::from(RwLock::from(parser)))
+pub async fn setup_parser(pool: &maiq_db::Pool) -> Result<SnapshotParser> {
+  let campuses = std::env::var("CAMPUSES").unwrap_or_else(|_| {
+    warn!(target: "setup", "env-var CAMPUSES not set; using {}", DEFAULT_CAMPUSES);
+    DEFAULT_CAMPUSES.into()
+  });
+
+  let mut builder = SnapshotParserBuilder::new()
+    .with_group_discovery(true)
+    .with_strict_dates(true);
+  let mut sources = vec![];
+  for campus in campuses.split(',').map(str::trim).filter(|campus| !campus.is_empty()) {
+    info!(target: "setup", "campus: {}", campus);
+    builder = builder
+      .with_source(Source::today(campus), format!("https://rsp.chemk.org/{}/today.htm", campus))?
+      .with_source(Source::next(campus), format!("https://rsp.chemk.org/{}/tomorrow.htm", campus))?;
+    sources.extend([Source::today(campus), Source::next(campus)]);
+  }
+
+  match std::env::var("GROUPS_PATH") {
+    Ok(path) => builder = builder.with_groups_file(path)?,
+    Err(_) => warn!(target: "setup", "env-var GROUPS_PATH not set"),
+  }
+
+  match std::env::var("TIMETABLE_PATH") {
+    Ok(path) => builder = builder.with_default_lectures_file(path)?,
+    Err(_) => warn!(target: "setup", "env-var TIMETABLE_PATH not set"),
+  }
+
+  // раскладка страницы из конфига: небольшие изменения вёрстки правятся без релиза
+  if let Ok(path) = std::env::var("LAYOUT_PATH") {
+    info!(target: "setup", "layout: {}", path);
+    builder = builder.with_layout_file(path)?;
+  }
+
+  // по умолчанию самая большая таблица: подпись под изменениями на некоторых страницах идёт отдельной таблицей
+  let selection = match std::env::var("TABLE_SELECTION") {
+    Ok(raw) => raw.parse().map_err(anyhow::Error::msg)?,
+    Err(_) => TableSelection::Largest,
+  };
+  info!(target: "setup", "table selection: {}", selection);
+  builder = builder.with_table_selection(selection);
+
+  // сравниваем с тем, о чём уже уведомили, чтобы изменения, пропущенные за время рестарта, всё равно разослать
+  for source in sources {
+    let entry = match SnapshotEntry::get_last_notified(&source, pool).await? {
+      Some(entry) => Some(entry),
+      None => SnapshotEntry::get_latest(&source, pool).await?,
+    };
+    if let Some(entry) = entry {
+      info!(target: "setup", "restored {} snapshot {} fetched at {}", source, entry.snapshot().id(), entry.fetched_at());
+      builder = builder.with_latest(source, entry.into_snapshot());
+    }
+  }
+
+  Ok(Arc::from(RwLock::from(builder.build()?)))
 }
 
 pub async fn start(bot: Bot, pool: maiq_db::Pool, parser: SnapshotParser) {
   let pool = Arc::new(pool);
-  let parser = start_parser_service(bot.clone(), parser, pool.clone());
+  let control = start_parser_service(bot.clone(), parser.clone(), pool.clone());
 
-  Dispatcher::builder(bot, dispatch_tree())
+  let mut dispatcher = Dispatcher::builder(bot, dispatch_tree())
     .worker_queue_size(16)
-    .dependencies(deps![parser, pool])
-    .build()
-    .dispatch()
-    .await
+    .dependencies(deps![parser, pool, control.clone()])
+    .build();
+
+  let shutdown = dispatcher.shutdown_token();
+  tokio::spawn(async move {
+    wait_for_shutdown_signal().await;
+    info!(target: "setup", "shutting down");
+    control.shutdown();
+    if let Ok(shutdown) = shutdown.shutdown() {
+      shutdown.await
+    }
+  });
+
+  dispatcher.dispatch().await;
+  info!(target: "setup", "stopped");
+}
+
+/// SIGTERM приходит при деплое, Ctrl-C - при локальном запуске
+async fn wait_for_shutdown_signal() {
+  let mut sigterm = signal(SignalKind::terminate()).expect("unable to listen for SIGTERM");
+  tokio::select! {
+    _ = sigterm.recv() => (),
+    _ = tokio::signal::ctrl_c() => (),
+  }
 }
 
 async fn ensure_webhook_not_set(bot: &Bot) -> Result<()> {
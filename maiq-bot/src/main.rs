@@ -5,9 +5,10 @@ async fn main() {
   color_eyre::install().unwrap();
   dotenvy::dotenv().ok();
   logger::init_logger(false);
+  maiq_bot::setup_timezone().expect("invalid timezone");
 
   let pool = maiq_db::pool().await.expect("unable to setup db");
-  let parser = maiq_bot::setup_parser().expect("unable to setup parser");
+  let parser = maiq_bot::setup_parser(&pool).await.expect("unable to setup parser");
 
   #[cfg(profile = "release")]
   tokio::time::sleep(std::time::Duration::from_secs(2)).await;
@@ -1,76 +1,177 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
 use teloxide::prelude::*;
+use teloxide::utils::html;
 use teloxide::ApiError;
 use teloxide::RequestError;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
 use tokio::task::JoinSet;
 
 use anyhow::Result;
 
+use crate::format::FormatDate;
 use crate::format::FormatSnapshot;
 use crate::reply;
 use crate::SnapshotParser;
+use crate::DEVELOPER_ID;
 
+use maiq_db::models::SnapshotEntry;
 use maiq_db::models::User;
 use maiq_db::Pool;
 use maiq_parser_next::prelude::*;
 
-macro_rules! run_shapshot_handler {
-  ($e: expr, $bot: expr, $pool: expr) => {
-    let res = match $e {
-      Ok(Some((snapshot, changes))) if !changes.is_empty() => on_update($bot.clone(), $pool.clone(), snapshot, changes).await,
-      Ok(Some(_)) => Ok(()),
-      Ok(None) => {
-        warn!("snapshot is None; is url set?");
-        Ok(())
-      },
-      Err(err) => on_error(err),
-    };
+pub fn start_parser_service(bot: Bot, parser: SnapshotParser, pool: Arc<Pool>) -> ParserHandle {
+  let repeating = RepeatingSnapshotParser::with_schedule(parser.clone(), poll_schedule());
+  let handle = repeating.handle();
+  let mut events = repeating.subscribe();
+  tokio::spawn(repeating.start());
+  let mut queues = SourceQueues::new({
+    let bot = bot.clone();
+    move |event| on_event(bot.clone(), pool.clone(), parser.clone(), event)
+  });
 
-    if let Err(err) = res {
-      error!(target: "rx-parser", "error during handling update: {:?}", err);
+  tokio::spawn(async move {
+    let mut reported = ReportedErrors::new();
+    loop {
+      match events.recv().await {
+        Ok(ParserEvent::FetchFailed { source, error, .. }) => on_error(&bot, &mut reported, &source, &error),
+        Ok(ParserEvent::Recovered { source, failures }) => {
+          info!(target: "rx-parser", "[{}] recovered after {} failures", source, failures);
+          reported.remove(&source);
+        }
+        Ok(ParserEvent::Tick { at }) => debug!(target: "rx-parser", "tick at {}", at),
+        Ok(event) => match event.source().cloned() {
+          Some(source) => queues.push(source, event),
+          None => warn!(target: "rx-parser", "unexpected event without source: {:?}", event),
+        },
+        Err(RecvError::Lagged(skipped)) => warn!(target: "rx-parser", "skipped {} parser events", skipped),
+        Err(RecvError::Closed) => break,
+      }
     }
-  };
+  });
+
+  handle
 }
 
-pub fn start_parser_service(bot: Bot, parser: SnapshotParser, pool: Arc<Pool>) -> SnapshotParser {
+/// Расписание опроса из `SCHEDULE_PATH`, иначе каждый день с интервалом `DELAY`
+fn poll_schedule() -> PollSchedule {
+  if let Ok(path) = std::env::var("SCHEDULE_PATH") {
+    match PollSchedule::read(&path) {
+      Ok(schedule) => return schedule,
+      Err(err) => error!(target: "rx-parser", "unable to read schedule {}: {}", path, err),
+    }
+  }
+
   let delay_secs = std::env::var("DELAY")
     .ok()
     .and_then(|v| v.parse().ok())
+    .filter(|&secs: &u64| secs > 0)
     .unwrap_or_else(|| {
-      warn!(target: "rx-parser", "env-var DELAY not set; using 300s");
+      warn!(target: "rx-parser", "env-var DELAY not set or invalid; using 300s");
       300
     });
+  PollSchedule::new(Duration::from_secs(delay_secs))
+}
 
-  let parser_clone = parser.clone();
+/// Очереди событий по источнику: события одного источника обрабатываются по порядку, разных - параллельно
+struct SourceQueues<T, F> {
+  queues: HashMap<Source, mpsc::UnboundedSender<T>>,
+  handle: F,
+}
 
-  tokio::spawn(async move {
-    let repeating = RepeatingSnapshotParser::with_interval(parser_clone, Duration::from_secs(delay_secs)).with_update_handler(
-      Box::new(move |today, next| {
-        let bot = bot.clone();
-        let pool = pool.clone();
-        tokio::spawn(async move {
-          run_shapshot_handler!(today, bot, pool);
-          run_shapshot_handler!(next, bot, pool);
-        });
-      }),
-    );
-    repeating.start().await
-  });
+impl<T, F, Fut> SourceQueues<T, F>
+where
+  T: Send + 'static,
+  F: Fn(T) -> Fut + Clone + Send + 'static,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  fn new(handle: F) -> Self {
+    Self { queues: HashMap::new(), handle }
+  }
 
-  parser
+  fn push(&mut self, source: Source, item: T) {
+    let queue = self.queues.entry(source.clone()).or_insert_with(|| {
+      let (tx, mut rx) = mpsc::unbounded_channel();
+      let handle = self.handle.clone();
+      tokio::spawn(async move {
+        while let Some(item) = rx.recv().await {
+          handle(item).await;
+        }
+      });
+      tx
+    });
+
+    if let Err(mpsc::error::SendError(item)) = queue.send(item) {
+      warn!(target: "rx-parser", "[{}] event queue closed; restarting", source);
+      self.queues.remove(&source);
+      self.push(source, item);
+    }
+  }
 }
 
-async fn on_update(bot: Bot, pool: Arc<Pool>, snapshot: Snapshot, changes: Vec<String>) -> Result<()> {
-  info!(target: "rx-parser", "snapshot: {} changes: {:?}", snapshot.id(), changes);
+async fn on_event(bot: Bot, pool: Arc<Pool>, parser: SnapshotParser, event: ParserEvent) {
+  let res = match event {
+    ParserEvent::Tick { .. } => Ok(()),
+    ParserEvent::SnapshotChanged { snapshot, diff, .. } => on_snapshot(bot, pool, parser, snapshot, diff).await,
+    ParserEvent::SourceUnavailable { source, error } => on_unavailable(&bot, &source, &error).await,
+    // ошибки и восстановление обрабатываются в цикле событий, там хранится, о чём уже сообщили
+    ParserEvent::FetchFailed { .. } | ParserEvent::Recovered { .. } => Ok(()),
+  };
+
+  if let Err(err) = res {
+    error!(target: "rx-parser", "error during handling update: {:?}", err);
+  }
+}
+
+/// Снапшот помечается уведомлённым только после рассылки: если процесс упадёт раньше, после рестарта изменение разошлётся
+/// снова. Если рассылка просто не удалась, парсер откатывается к последнему разосланному снапшоту и найдёт то же
+/// изменение на следующем тике
+async fn on_snapshot(bot: Bot, pool: Arc<Pool>, parser: SnapshotParser, snapshot: Snapshot, diff: SnapshotDiff) -> Result<()> {
+  let res = notify(bot, pool.clone(), &snapshot, diff).await;
+  if res.is_err() {
+    let notified = match snapshot.source() {
+      Some(source) => SnapshotEntry::get_last_notified(source, &pool)
+        .await?
+        .map(|entry| entry.into_snapshot()),
+      None => None,
+    };
+    parser.write().await.revert(&snapshot, notified);
+  }
+  res
+}
+
+async fn notify(bot: Bot, pool: Arc<Pool>, snapshot: &Snapshot, diff: SnapshotDiff) -> Result<()> {
+  SnapshotEntry::insert(snapshot, &pool).await?;
+  if !diff.is_empty() {
+    on_update(bot, pool.clone(), snapshot.clone(), diff).await?;
+  }
+  SnapshotEntry::set_notified(snapshot, &pool).await?;
+  Ok(())
+}
+
+async fn on_update(bot: Bot, pool: Arc<Pool>, snapshot: Snapshot, diff: SnapshotDiff) -> Result<()> {
+  info!(
+    target: "rx-parser",
+    "snapshot: {} changes: {:?}",
+    snapshot.id(),
+    diff.groups().map(|group| group.name()).collect::<Vec<&str>>()
+  );
   let users = User::get_all_notified(&pool).await?;
+  let campus = snapshot.source().map(|source| source.campus().to_string());
   let snapshot = Arc::new(snapshot);
   let mut tasks = JoinSet::new();
   users
     .into_iter()
-    .map(|(id, mut groups)| {
-      groups.retain(|g| changes.contains(g));
+    .filter(|(_, _, campuses)| match campus.as_ref() {
+      Some(campus) => campuses.is_empty() || campuses.contains(campus),
+      None => true,
+    })
+    .map(|(id, mut groups, _)| {
+      groups.retain(|g| diff.has_group(g));
       (id, groups)
     })
     .filter(|(_, groups)| !groups.is_empty())
@@ -95,10 +196,69 @@ async fn on_update(bot: Bot, pool: Arc<Pool>, snapshot: Snapshot, changes: Vec<S
   Ok(())
 }
 
-fn on_error(err: maiq_parser_next::error::Error) -> Result<()> {
-  if !err.can_be_skipped() {
-    warn!(target: "rx-parser", "error during parsing: {:?}", err);
+/// Последняя ошибка, о которой сообщили разработчику, по источнику - чтобы не слать одно и то же на каждом тике.
+/// Живёт в цикле событий [`start_parser_service`]
+type ReportedErrors = HashMap<Source, String>;
+
+/// Пропускаемые ошибки только логируются, временные сетевые - логируются как предупреждения,
+/// а о непонятном содержимом страницы и прочих ошибках пишем разработчику, один раз до следующего успешного разбора
+fn on_error(bot: &Bot, reported: &mut ReportedErrors, source: &Source, err: &maiq_parser_next::error::Error) {
+  if err.can_be_skipped() {
+    debug!(target: "rx-parser", "[{}] skipped: {}", source, err);
+    return;
+  }
+
+  if err.is_transient() {
+    warn!(target: "rx-parser", "[{}] transient error: {}", source, err);
+    return;
+  }
+
+  error!(target: "rx-parser", "[{}] error during parsing: {}", source, err);
+  if !should_report(reported, source, err.to_string()) {
+    return;
+  }
+
+  let kind = if err.is_unexpected_content() { "Страница изменилась" } else { "Ошибка парсера" };
+  let message = format!("{} ({})\n\n<code>{}</code>", kind, source, html::escape(&err.to_string()));
+  tokio::spawn(notify_developer(bot.clone(), message));
+}
+
+fn should_report(reported: &mut ReportedErrors, source: &Source, message: String) -> bool {
+  match reported.get(source) {
+    Some(last) if *last == message => false,
+    _ => {
+      reported.insert(source.clone(), message);
+      true
+    }
+  }
+}
+
+async fn notify_developer(bot: Bot, message: String) {
+  let res = bot
+    .send_message(UserId(DEVELOPER_ID), message)
+    .parse_mode(teloxide::types::ParseMode::Html)
+    .disable_web_page_preview(true)
+    .await;
+  if let Err(err) = res {
+    error!(target: "rx-parser", "unable to notify developer: {:?}", err);
+  }
+}
+
+/// Временные ошибки сами по себе разработчику не пишутся, но если источник не отвечает несколько тиков подряд - стоит знать
+async fn on_unavailable(bot: &Bot, source: &Source, err: &maiq_parser_next::error::Error) -> Result<()> {
+  warn!(target: "rx-parser", "[{}] source is unavailable: {}", source, err);
+  if !err.is_transient() {
+    return Ok(());
   }
+
+  bot
+    .send_message(
+      UserId(DEVELOPER_ID),
+      format!("Источник недоступен ({})\n\n<code>{}</code>", source, html::escape(&err.to_string())),
+    )
+    .parse_mode(teloxide::types::ParseMode::Html)
+    .disable_web_page_preview(true)
+    .await?;
   Ok(())
 }
 
@@ -127,21 +287,69 @@ async fn try_send(bot: Bot, pool: Arc<Pool>, snapshot: Arc<Snapshot>, chat_id: i
     };
   }
 
+  // группы, у которых в изменениях не осталось пар, из снапшота пропадают, но об этом тоже нужно сообщить
+  let format = |group_name: &str| match FormatSnapshot::select_group(&snapshot, group_name) {
+    Some(format) => format.to_string(),
+    None => reply!("snapshot/no_lectures.md", date = FormatDate(&snapshot.date())),
+  };
+
   match groups.len() {
     1 => {
-      let group_name = groups.first().unwrap();
-      if let Some(format) = FormatSnapshot::select_group(&snapshot, group_name) {
-        send!(format.to_string());
-      }
+      send!(format(groups.first().unwrap()));
     }
     _ => {
-      for format in groups
-        .iter()
-        .filter_map(|group| FormatSnapshot::select_group(&snapshot, group))
-      {
-        send!(reply!("snapshot/many_groups.md", group_name = format.group_name(), formatted = format.to_string()));
+      for group_name in groups.iter() {
+        send!(reply!("snapshot/many_groups.md", group_name = group_name, formatted = format(group_name)));
       }
     }
   }
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  fn report_once_per_error() {
+    let mut reported = ReportedErrors::new();
+    let today = Source::today("4korp");
+    let next = Source::next("4korp");
+    assert!(should_report(&mut reported, &today, "a".into()));
+    assert!(!should_report(&mut reported, &today, "a".into()));
+    assert!(should_report(&mut reported, &next, "a".into()));
+    assert!(should_report(&mut reported, &today, "b".into()));
+
+    reported.remove(&today);
+    assert!(should_report(&mut reported, &today, "b".into()));
+  }
+
+  #[tokio::test]
+  async fn queue_keeps_order_per_source() {
+    let handled = Arc::new(std::sync::Mutex::new(vec![]));
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel();
+    let mut queues = SourceQueues::new({
+      let handled = handled.clone();
+      move |(source, id, delay): (Source, u32, u64)| {
+        let handled = handled.clone();
+        let done_tx = done_tx.clone();
+        async move {
+          tokio::time::sleep(Duration::from_millis(delay)).await;
+          handled.lock().unwrap().push((source, id));
+          done_tx.send(()).unwrap();
+        }
+      }
+    });
+
+    let today = Source::today("4korp");
+    let next = Source::next("4korp");
+    queues.push(today.clone(), (today.clone(), 1, 100));
+    queues.push(today.clone(), (today.clone(), 2, 0));
+    queues.push(next.clone(), (next.clone(), 3, 0));
+    for _ in 0..3 {
+      done_rx.recv().await.unwrap();
+    }
+
+    assert_eq!(*handled.lock().unwrap(), vec![(next, 3), (today.clone(), 1), (today, 2)]);
+  }
+}
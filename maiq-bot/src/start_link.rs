@@ -0,0 +1,106 @@
+use maiq_parser_next::utils::hash::StableHasher;
+
+/// Список групп, по индексам в котором кодировались старые ссылки вида `g0g12`. Не менять
+const LEGACY_GROUP_NAMES: [&str; 34] = [
+  "Ит1-23", "Ит3-23", "Ит1-22", "Са1-22", "Са3-22", "С1-22", "С3-22", "Ир1-22", "Ир3-22", "Ир5-22", "Са1-21", "Са3-21", "С1-21",
+  "С3-21", "Ип1-21", "Ип3-21", "Ип5-21", "Ир1-21", "Ир3-21", "Ир5-21", "С1-20", "С3-20", "Кс1-20", "Кс3-20", "Кс5-20", "Ип1-20",
+  "Ип3-20", "Ир1-20", "Ир3-20", "Ир5-20", "С1-19", "С3-19", "ЗК1-21", "ЗК1-22",
+];
+
+const PREFIX: char = 'h';
+const LEGACY_PREFIX: char = 'g';
+
+/// Больше групп не влезает в 64 символа, которые Telegram разрешает для параметра `/start`
+pub const MAX_GROUPS: usize = 9;
+
+/// Кодирует группы в параметр `/start`: `h` и 6 hex-символов стабильного хеша названия на каждую группу.
+/// Хеш не зависит от порядка и состава списка групп, поэтому ссылки переживают его изменение.
+/// `None`, если групп больше [`MAX_GROUPS`]
+pub fn encode<S: AsRef<str>, I: IntoIterator<Item = S>>(groups: I) -> Option<String> {
+  let groups = groups.into_iter().collect::<Vec<S>>();
+  if groups.len() > MAX_GROUPS {
+    return None;
+  }
+
+  Some(
+    groups
+      .iter()
+      .map(|group| format!("{}{}", PREFIX, group_hash(group.as_ref())))
+      .collect(),
+  )
+}
+
+/// Достаёт из параметра `/start` группы из `known`. Понимает и старые ссылки с индексами. Хеш, которому соответствует
+/// несколько групп, пропускается: лучше не добавить группу, чем добавить чужую
+pub fn decode<S: AsRef<str>>(payload: &str, known: &[S]) -> Vec<String> {
+  if payload.starts_with(LEGACY_PREFIX) {
+    return payload
+      .split(LEGACY_PREFIX)
+      .filter_map(|idx| idx.parse::<usize>().ok())
+      .filter_map(|idx| LEGACY_GROUP_NAMES.get(idx))
+      .map(|group| group.to_string())
+      .collect();
+  }
+
+  payload
+    .split(PREFIX)
+    .filter(|hash| !hash.is_empty())
+    .filter_map(|hash| {
+      let mut matches = known.iter().filter(|group| group_hash(group.as_ref()) == hash);
+      match (matches.next(), matches.next()) {
+        (Some(group), None) => Some(group.as_ref().to_string()),
+        (Some(_), Some(_)) => {
+          warn!(target: "start", "ambiguous group hash {}", hash);
+          None
+        }
+        _ => None,
+      }
+    })
+    .collect()
+}
+
+fn group_hash(name: &str) -> String {
+  let mut hash = StableHasher::new();
+  hash.write_str(name);
+  format!("{:06x}", hash.finish() & 0xffffff)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  fn roundtrip() {
+    let known = ["Ир3-21", "Ир1-21", "С1-22"];
+    let payload = encode(["Ир3-21", "С1-22"]).unwrap();
+    assert_eq!(decode(&payload, &known), vec!["Ир3-21", "С1-22"]);
+  }
+
+  #[rstest]
+  fn survives_list_change() {
+    let payload = encode(["Ир3-21"]).unwrap();
+    assert_eq!(decode(&payload, &["Ит1-24", "Ир3-21"]), vec!["Ир3-21"]);
+    assert!(decode(&payload, &["Ит1-24"]).is_empty());
+  }
+
+  #[rstest]
+  fn legacy() {
+    assert_eq!(decode::<&str>("g18g0", &[]), vec!["Ир3-21", "Ит1-23"]);
+  }
+
+  #[rstest]
+  fn fits_telegram_limit() {
+    let payload = encode(LEGACY_GROUP_NAMES.iter().take(MAX_GROUPS)).unwrap();
+    assert!(payload.len() <= 64);
+    assert!(payload.chars().all(|c| c.is_ascii_alphanumeric()));
+    assert!(encode(LEGACY_GROUP_NAMES.iter().take(MAX_GROUPS + 1)).is_none());
+  }
+
+  #[rstest]
+  fn ambiguous() {
+    assert_eq!(group_hash("Ир3-963"), group_hash("Ир0-2220"));
+    let payload = encode(["Ир3-963", "Ир3-21"]).unwrap();
+    assert_eq!(decode(&payload, &["Ир3-963", "Ир0-2220", "Ир3-21"]), vec!["Ир3-21"]);
+    assert_eq!(decode(&payload, &["Ир3-963", "Ир3-21"]), vec!["Ир3-963", "Ир3-21"]);
+  }
+}
@@ -1,27 +1,53 @@
 use std::env;
 
+use maiq_parser_next::parser::fetch::FsFetcher;
 use maiq_parser_next::parser::SnapshotParserBuilder;
 use maiq_parser_next::prelude::*;
+use maiq_time::set_timezone;
 
 use owo_colors::OwoColorize;
 
 #[tokio::main]
 async fn main() {
   pretty_env_logger::init();
-  let parser = SnapshotParserBuilder::new()
+  if let Ok(tz) = env::var("TIMEZONE") {
+    set_timezone(tz.parse().unwrap());
+  }
+
+  let mut builder = SnapshotParserBuilder::new()
     .with_today_url("https://rsp.chemk.org/4korp/today.htm")
-    .unwrap()
-    .build::<SnapshotParser4>()
     .unwrap();
 
+  if let Ok(dir) = env::var("PAGES_DIR") {
+    builder = builder.with_fetcher(FsFetcher::new(dir));
+  }
+
+  if let Ok(path) = env::var("TIMETABLE_PATH") {
+    builder = builder.with_default_lectures_file(path).unwrap();
+  }
+
+  let parser = builder.build::<SnapshotParser4>().unwrap();
+
   let today = parser.fetch_today().await;
-  let snapshot = today.unwrap().unwrap().0;
+  let (snapshot, _, report) = today.unwrap().unwrap();
 
   if env::args().len() > 1 {
     print_group(snapshot.group(&env::args().nth(1).unwrap()).expect("no such group"));
     return;
   }
   print_snapshot(&snapshot);
+  print_report(&report);
+}
+
+fn print_report(r: &ParseReport) {
+  if r.is_empty() {
+    return;
+  }
+
+  println!("{} ({})", "Замечания".bright_yellow(), r.len());
+  for entry in r.entries() {
+    println!("\t{} {}", entry.reason().yellow(), format!("[{}]", entry.cells().join(" | ")).bright_black());
+  }
 }
 
 fn print_snapshot(s: &Snapshot) {
@@ -0,0 +1,32 @@
+create table snapshots(
+  id varchar(16) not null primary key,
+  date date not null,
+  fetched_at timestamp not null default(datetime())
+);
+create table snapshot_groups(
+  id integer not null primary key autoincrement,
+  snapshot_ref varchar(16) not null,
+  group_id varchar(16) not null,
+  name varchar(64) not null,
+  position int not null,
+  constraint fk_snapshot_ref foreign key(snapshot_ref) references snapshots(id)
+);
+create table snapshot_lectures(
+  id integer not null primary key autoincrement,
+  group_ref int not null,
+  position int not null,
+  lecture_order varchar(32),
+  name text not null,
+  classroom varchar(64),
+  subgroup varchar(32),
+  teacher varchar(256),
+  constraint fk_group_ref foreign key(group_ref) references snapshot_groups(id)
+);
+create table latest_snapshots(
+  source varchar(64) not null primary key,
+  snapshot_ref varchar(16) not null,
+  constraint fk_snapshot_ref foreign key(snapshot_ref) references snapshots(id)
+);
+create index ix_snapshots_date on snapshots(date);
+create index ix_snapshot_groups_name on snapshot_groups(name);
+create unique index ux_snapshot_groups_pair on snapshot_groups(snapshot_ref, name);
@@ -0,0 +1,8 @@
+alter table snapshots add column source varchar(64);
+update latest_snapshots set source = '4korp/' || source where source in ('today', 'next');
+create table target_campuses(
+  id integer not null primary key autoincrement,
+  user_ref bigint not null,
+  campus varchar(64) not null
+);
+create unique index ux_target_campuses_pair on target_campuses(user_ref, campus);
@@ -0,0 +1,7 @@
+create table notified_snapshots(
+  source varchar(64) not null primary key,
+  snapshot_ref varchar(16) not null,
+  notified_at timestamp not null default(datetime()),
+  constraint fk_snapshot_ref foreign key(snapshot_ref) references snapshots(id)
+);
+insert into notified_snapshots(source, snapshot_ref) select source, snapshot_ref from latest_snapshots;
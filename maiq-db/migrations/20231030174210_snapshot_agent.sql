@@ -0,0 +1 @@
+alter table snapshots add column agent varchar(32);
@@ -5,7 +5,8 @@ select
   users.created_at,
   configs.is_broadcast_enabled,
   configs.is_notifies_enabled,
-  group_names
+  group_names,
+  campus_names
 from users
   join configs on users.config_ref = configs.id
   left join (
@@ -13,5 +14,10 @@ from users
     from target_groups
       join groups on target_groups.group_name_ref = groups.id
     group by user_ref
-  ) on user_ref = users.id
+  ) as target on target.user_ref = users.id
+  left join (
+    select user_ref, group_concat(campus) as campus_names
+    from target_campuses
+    group by user_ref
+  ) as campuses on campuses.user_ref = users.id
 where users.id = $1
\ No newline at end of file
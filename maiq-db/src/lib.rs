@@ -1,7 +1,6 @@
 pub mod error;
 pub mod models;
 pub mod queries;
-mod utils;
 
 use error::Error;
 use log::*;
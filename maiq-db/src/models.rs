@@ -1,5 +1,6 @@
-use crate::utils::*;
 use getset::*;
+use maiq_parser_next::snapshot::Snapshot;
+use maiq_time::*;
 
 #[derive(Getters, CopyGetters, MutGetters, Clone, Debug)]
 pub struct User {
@@ -54,6 +55,8 @@ pub struct Config {
   pub(crate) is_broadcast_enabled: bool,
 
   pub(crate) target_groups: Vec<String>,
+
+  pub(crate) target_campuses: Vec<String>,
 }
 
 impl Config {
@@ -64,4 +67,28 @@ impl Config {
   pub fn has_group<S: AsRef<str>>(&self, name: S) -> bool {
     self.groups().iter().any(|g| g == name.as_ref())
   }
+
+  /// Корпуса, о которых присылать уведомления. Пустой список - все корпуса
+  pub fn campuses(&self) -> &[String] {
+    &self.target_campuses
+  }
+
+  pub fn has_campus<S: AsRef<str>>(&self, campus: S) -> bool {
+    self.campuses().is_empty() || self.campuses().iter().any(|c| c == campus.as_ref())
+  }
+}
+
+#[derive(Getters, Clone, Debug)]
+pub struct SnapshotEntry {
+  #[getset(get = "pub")]
+  pub(crate) snapshot: Snapshot,
+
+  #[getset(get = "pub")]
+  pub(crate) fetched_at: DateTime,
+}
+
+impl SnapshotEntry {
+  pub fn into_snapshot(self) -> Snapshot {
+    self.snapshot
+  }
 }
@@ -1,12 +1,17 @@
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
 use log::*;
+use maiq_parser_next::parser::source::Source;
+use maiq_parser_next::snapshot::*;
+use maiq_time::*;
 use sqlx::*;
 
 use crate::models::*;
-use crate::utils::*;
 use crate::Db;
 use crate::Result;
 
-pub type UserEntry = (i64, Vec<String>);
+/// Id чата, группы и корпуса (пустой список - все корпуса)
+pub type UserEntry = (i64, Vec<String>, Vec<String>);
 
 macro_rules! parse_user {
   ($row: expr) => {{
@@ -25,6 +30,13 @@ macro_rules! parse_user {
           .filter(|s| !s.is_empty())
           .map(Into::into)
           .collect::<Vec<String>>(),
+        target_campuses: $row
+          .campus_names
+          .unwrap_or_default()
+          .split(',')
+          .filter(|s| !s.is_empty())
+          .map(Into::into)
+          .collect::<Vec<String>>(),
         chat_id: $row.id,
       },
       created_at,
@@ -42,7 +54,7 @@ impl User {
     Ok(self)
   }
 
-  pub async fn get_by_id_or_create<'c>(id: i64, pool: &Pool<Db>) -> Result<Self> {
+  pub async fn get_by_id_or_create(id: i64, pool: &Pool<Db>) -> Result<Self> {
     let row = sqlx::query_file!("sql/get_user_by_id.sql", id)
       .fetch_optional(pool)
       .await?;
@@ -63,7 +75,11 @@ impl User {
   pub async fn get_all_notified(pool: &Pool<Db>) -> Result<Vec<UserEntry>> {
     let entries = sqlx::query!(
       r#"
-        select users.id as id, group_concat(group_name) as groups from users 
+        select
+          users.id as id,
+          group_concat(group_name) as "groups!: String",
+          (select group_concat(campus) from target_campuses where target_campuses.user_ref = users.id) as "campuses: String"
+        from users
         join configs on configs.id = users.config_ref
         join target_groups on target_groups.user_ref = users.id
         join groups on groups.id = target_groups.group_name_ref
@@ -74,7 +90,15 @@ impl User {
     .fetch_all(pool)
     .await?
     .into_iter()
-    .map(|row| (row.id, row.groups.split(',').map(Into::into).collect()))
+    .map(|row| {
+      let campuses = row.campuses.unwrap_or_default();
+      let campuses = campuses
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(Into::into)
+        .collect();
+      (row.id, row.groups.split(',').map(Into::into).collect(), campuses)
+    })
     .collect();
 
     Ok(entries)
@@ -175,4 +199,275 @@ impl Config {
     self.target_groups.retain(|n| n != name);
     Ok(())
   }
+
+  pub async fn add_campus<S: AsRef<str>>(&mut self, campus: S, pool: &Pool<Db>) -> Result<()> {
+    let campus = campus.as_ref();
+    if self.target_campuses.iter().any(|c| c == campus) {
+      return Ok(());
+    };
+
+    sqlx::query!("insert into target_campuses(user_ref, campus) values ($1, $2)", self.chat_id, campus)
+      .execute(pool)
+      .await?;
+
+    self.target_campuses.push(campus.into());
+    Ok(())
+  }
+
+  pub async fn remove_campus<S: AsRef<str>>(&mut self, campus: S, pool: &Pool<Db>) -> Result<()> {
+    let campus = campus.as_ref();
+    sqlx::query!("delete from target_campuses where user_ref = $1 and campus = $2", self.chat_id, campus)
+      .execute(pool)
+      .await?;
+
+    self.target_campuses.retain(|c| c != campus);
+    Ok(())
+  }
+}
+
+pub type GroupEntry = (DateTime, Group);
+
+struct SnapshotRow {
+  snapshot_ref: String,
+  date: NaiveDate,
+  source: Option<String>,
+  agent: Option<String>,
+  fetched_at: NaiveDateTime,
+  group_ref: Option<i64>,
+  group_name: Option<String>,
+  lecture_order: Option<String>,
+  lecture_name: Option<String>,
+  classroom: Option<String>,
+  subgroup: Option<String>,
+  teacher: Option<String>,
+}
+
+macro_rules! query_snapshot_rows {
+  ($filter: literal, $($args: expr),*) => {
+    sqlx::query_as!(
+      SnapshotRow,
+      r#"
+        select
+          snapshots.id as snapshot_ref,
+          snapshots.date,
+          snapshots.source,
+          snapshots.agent,
+          snapshots.fetched_at,
+          snapshot_groups.id as "group_ref?",
+          snapshot_groups.name as "group_name?",
+          snapshot_lectures.lecture_order,
+          snapshot_lectures.name as "lecture_name?",
+          snapshot_lectures.classroom,
+          snapshot_lectures.subgroup,
+          snapshot_lectures.teacher
+        from snapshots
+          left join snapshot_groups on snapshot_groups.snapshot_ref = snapshots.id
+          left join snapshot_lectures on snapshot_lectures.group_ref = snapshot_groups.id
+      "# + $filter + r#"
+        order by snapshots.fetched_at, snapshots.rowid, snapshot_groups.position, snapshot_lectures.position
+      "#,
+      $($args),*
+    )
+  };
+}
+
+fn hex_id(id: u64) -> String {
+  format!("{:016x}", id)
+}
+
+/// Собирает снапшоты из упорядоченных строк запроса `query_snapshot_rows!`. У снапшота без групп одна строка без группы
+fn collect_snapshots(rows: Vec<SnapshotRow>) -> Vec<SnapshotEntry> {
+  let mut entries = vec![];
+  let mut rows = rows.into_iter().peekable();
+
+  while let Some(first) = rows.peek() {
+    let snapshot_ref = first.snapshot_ref.clone();
+    let date = DateTime::from_naive_date(first.date);
+    let fetched_at = DateTime::from_naive(first.fetched_at);
+    let source = first
+      .source
+      .as_deref()
+      .and_then(|source| source.parse::<Source>().ok());
+    let agent = first.agent.clone();
+    let mut groups: Vec<(i64, String, Vec<Lecture>)> = vec![];
+
+    while let Some(row) = rows.next_if(|row| row.snapshot_ref == snapshot_ref) {
+      let (Some(group_ref), Some(group_name)) = (row.group_ref, row.group_name) else { continue };
+      if groups.last().map(|(known, ..)| *known != group_ref).unwrap_or(true) {
+        groups.push((group_ref, group_name, vec![]));
+      }
+
+      if let Some(name) = row.lecture_name {
+        let lectures = &mut groups.last_mut().unwrap().2;
+        lectures.push(Lecture::new(
+          row.lecture_order.map(Into::into),
+          name.into(),
+          row.classroom.map(Into::into),
+          row.subgroup.map(Into::into),
+          row.teacher.map(Into::into),
+        ));
+      }
+    }
+
+    let groups = groups
+      .into_iter()
+      .map(|(_, name, lectures)| Group::new(&name, lectures))
+      .collect();
+    let snapshot = match source {
+      Some(source) => Snapshot::new(date, groups).with_source(source),
+      None => Snapshot::new(date, groups),
+    };
+    let snapshot = match agent {
+      Some(agent) => snapshot.with_agent(agent),
+      None => snapshot,
+    };
+    entries.push(SnapshotEntry { snapshot, fetched_at });
+  }
+
+  entries
+}
+
+impl SnapshotEntry {
+  /// Сохраняет снапшот, если такого ещё нет, и помечает его последним для его источника
+  pub async fn insert(snapshot: &Snapshot, pool: &Pool<Db>) -> Result<()> {
+    let id = hex_id(snapshot.id());
+    let date = snapshot.date().date_naive();
+    let source = snapshot.source().map(|source| source.to_string());
+    let agent = snapshot.agent();
+    let mut tx = pool.begin().await?;
+
+    let inserted =
+      sqlx::query!("insert or ignore into snapshots(id, date, source, agent) values ($1, $2, $3, $4)", id, date, source, agent)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    if inserted > 0 {
+      info!(target: "db", "inserting new snapshot {} for {}", id, date);
+      for (position, group) in snapshot.groups().enumerate() {
+        let group_id = hex_id(group.id());
+        let name = group.name();
+        let position = position as i64;
+        let group_ref = sqlx::query!(
+          r#"
+            insert into snapshot_groups(snapshot_ref, group_id, name, position)
+            values ($1, $2, $3, $4)
+          "#,
+          id,
+          group_id,
+          name,
+          position
+        )
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        for (position, lecture) in group.lectures().enumerate() {
+          let position = position as i64;
+          let order = lecture.order();
+          let name = lecture.name();
+          let classroom = lecture.classroom();
+          let subgroup = lecture.subgroup();
+          let teacher = lecture.teacher();
+          sqlx::query!(
+            r#"
+              insert into snapshot_lectures(group_ref, position, lecture_order, name, classroom, subgroup, teacher)
+              values ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            group_ref,
+            position,
+            order,
+            name,
+            classroom,
+            subgroup,
+            teacher
+          )
+          .execute(&mut *tx)
+          .await?;
+        }
+      }
+    }
+
+    if let Some(source) = source {
+      sqlx::query!(
+        r#"
+          insert into latest_snapshots(source, snapshot_ref) values ($1, $2)
+          on conflict(source) do update set snapshot_ref = excluded.snapshot_ref
+        "#,
+        source,
+        id
+      )
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+  }
+
+  pub async fn get_by_id(id: u64, pool: &Pool<Db>) -> Result<Option<Self>> {
+    let id = hex_id(id);
+    let rows = query_snapshot_rows!("where snapshots.id = $1", id)
+      .fetch_all(pool)
+      .await?;
+    Ok(collect_snapshots(rows).pop())
+  }
+
+  pub async fn get_latest(source: &Source, pool: &Pool<Db>) -> Result<Option<Self>> {
+    let source = source.to_string();
+    let rows = query_snapshot_rows!("where snapshots.id = (select snapshot_ref from latest_snapshots where source = $1)", source)
+      .fetch_all(pool)
+      .await?;
+    Ok(collect_snapshots(rows).pop())
+  }
+
+  /// Помечает, что об этом снапшоте пользователи уже уведомлены. Снапшот должен быть сохранён
+  pub async fn set_notified(snapshot: &Snapshot, pool: &Pool<Db>) -> Result<()> {
+    let Some(source) = snapshot.source().map(|source| source.to_string()) else { return Ok(()) };
+    let id = hex_id(snapshot.id());
+    sqlx::query!(
+      r#"
+        insert into notified_snapshots(source, snapshot_ref) values ($1, $2)
+        on conflict(source) do update set snapshot_ref = excluded.snapshot_ref, notified_at = datetime()
+      "#,
+      source,
+      id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+  }
+
+  /// Последний снапшот источника, о котором уведомили пользователей
+  pub async fn get_last_notified(source: &Source, pool: &Pool<Db>) -> Result<Option<Self>> {
+    let source = source.to_string();
+    let rows =
+      query_snapshot_rows!("where snapshots.id = (select snapshot_ref from notified_snapshots where source = $1)", source)
+        .fetch_all(pool)
+        .await?;
+    Ok(collect_snapshots(rows).pop())
+  }
+
+  /// Все сохранённые версии расписания на дату, от старых к новым
+  pub async fn get_by_date(date: NaiveDate, pool: &Pool<Db>) -> Result<Vec<Self>> {
+    let rows = query_snapshot_rows!("where snapshots.date = $1", date)
+      .fetch_all(pool)
+      .await?;
+    Ok(collect_snapshots(rows))
+  }
+
+  /// Все сохранённые версии расписания группы, от старых к новым
+  pub async fn get_group_history(name: &str, pool: &Pool<Db>) -> Result<Vec<GroupEntry>> {
+    let rows = query_snapshot_rows!("where snapshot_groups.name = $1", name)
+      .fetch_all(pool)
+      .await?;
+    let history = collect_snapshots(rows)
+      .into_iter()
+      .filter_map(|entry| {
+        let date = entry.snapshot.date();
+        entry.snapshot.groups().next().cloned().map(|group| (date, group))
+      })
+      .collect();
+    Ok(history)
+  }
 }
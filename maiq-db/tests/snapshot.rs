@@ -0,0 +1,145 @@
+use rstest::*;
+
+use chrono::prelude::*;
+use maiq_db::models::*;
+use maiq_db::Result;
+use maiq_db::*;
+use maiq_parser_next::parser::source::Source;
+use maiq_parser_next::snapshot::*;
+
+#[fixture]
+async fn pool() -> Pool {
+  std::env::set_var("SQLITE_PATH", "sqlite::memory:");
+  maiq_db::pool().await.expect("unable to create db")
+}
+
+fn snapshot(day: u32, classroom: &str, source: &str) -> Snapshot {
+  let date = FixedOffset::east_opt(3600 * 3)
+    .unwrap()
+    .with_ymd_and_hms(2023, 10, day, 0, 0, 0)
+    .unwrap();
+  let lecture = |order: &str, name: &str, subgroup: Option<&str>| {
+    Lecture::new(Some(order.into()), name.into(), Some(classroom.into()), subgroup.map(Into::into), Some("Иванов И.И.".into()))
+  };
+  Snapshot::new(
+    date,
+    vec![
+      Group::new("Ир3-21", vec![lecture("1", "Lecture1", Some("1")), lecture("2", "Lecture2", None)]),
+      Group::new("Ир1-21", vec![lecture("3", "Lecture3", None)]),
+    ],
+  )
+  .with_source(source.parse().unwrap())
+}
+
+#[rstest]
+#[tokio::test]
+async fn insert_and_get(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let snapshot = snapshot(18, "214", "4korp/today").with_agent("parser4");
+  SnapshotEntry::insert(&snapshot, &pool).await?;
+
+  let entry = SnapshotEntry::get_by_id(snapshot.id(), &pool).await?.unwrap();
+  assert_eq!(entry.snapshot().id(), snapshot.id());
+  assert_eq!(entry.snapshot().date(), snapshot.date());
+  assert_eq!(entry.snapshot().source(), snapshot.source());
+  assert_eq!(entry.snapshot().agent(), Some("parser4"));
+  assert_eq!(
+    entry
+      .snapshot()
+      .group("Ир3-21")
+      .unwrap()
+      .lectures()
+      .collect::<Vec<_>>(),
+    snapshot.group("Ир3-21").unwrap().lectures().collect::<Vec<_>>()
+  );
+  Ok(())
+}
+
+#[rstest]
+#[tokio::test]
+async fn without_groups(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let date = snapshot(18, "214", "4korp/today").date();
+  let snapshot = Snapshot::new(date, vec![]).with_source(Source::today("4korp"));
+  SnapshotEntry::insert(&snapshot, &pool).await?;
+  SnapshotEntry::set_notified(&snapshot, &pool).await?;
+
+  let entry = SnapshotEntry::get_by_id(snapshot.id(), &pool).await?.unwrap();
+  assert_eq!(entry.snapshot().id(), snapshot.id());
+  assert_eq!(entry.snapshot().groups().len(), 0);
+  let notified = SnapshotEntry::get_last_notified(&Source::today("4korp"), &pool).await?;
+  assert_eq!(notified.map(|e| e.snapshot().id()), Some(snapshot.id()));
+  Ok(())
+}
+
+#[rstest]
+#[tokio::test]
+async fn insert_is_idempotent(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let snapshot = snapshot(18, "214", "4korp/today");
+  SnapshotEntry::insert(&snapshot, &pool).await?;
+  SnapshotEntry::insert(&snapshot, &pool).await?;
+
+  let entries = SnapshotEntry::get_by_date(snapshot.date().date_naive(), &pool).await?;
+  assert_eq!(entries.len(), 1);
+  Ok(())
+}
+
+#[rstest]
+#[tokio::test]
+async fn latest_per_source(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let today = snapshot(18, "214", "4korp/today");
+  let next = snapshot(19, "214", "4korp/next");
+  let updated_next = snapshot(19, "301", "4korp/next");
+  let other_campus = snapshot(18, "214", "1korp/today");
+  SnapshotEntry::insert(&today, &pool).await?;
+  SnapshotEntry::insert(&next, &pool).await?;
+  SnapshotEntry::insert(&updated_next, &pool).await?;
+  SnapshotEntry::insert(&other_campus, &pool).await?;
+
+  let pool = &pool;
+  let latest = |source: Source| async move { SnapshotEntry::get_latest(&source, pool).await };
+  assert_eq!(latest(Source::today("4korp")).await?.map(|e| e.snapshot().id()), Some(today.id()));
+  assert_eq!(latest(Source::next("4korp")).await?.map(|e| e.snapshot().id()), Some(updated_next.id()));
+  assert_eq!(latest(Source::today("1korp")).await?.map(|e| e.snapshot().id()), Some(other_campus.id()));
+  assert!(latest(Source::next("1korp")).await?.is_none());
+  Ok(())
+}
+
+#[rstest]
+#[tokio::test]
+async fn last_notified(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let notified = snapshot(18, "214", "4korp/today");
+  let fetched = snapshot(18, "301", "4korp/today");
+  SnapshotEntry::insert(&notified, &pool).await?;
+  SnapshotEntry::set_notified(&notified, &pool).await?;
+  SnapshotEntry::insert(&fetched, &pool).await?;
+
+  let source = Source::today("4korp");
+  let last_notified = SnapshotEntry::get_last_notified(&source, &pool).await?;
+  assert_eq!(last_notified.map(|e| e.snapshot().id()), Some(notified.id()));
+  let latest = SnapshotEntry::get_latest(&source, &pool).await?;
+  assert_eq!(latest.map(|e| e.snapshot().id()), Some(fetched.id()));
+  assert!(SnapshotEntry::get_last_notified(&Source::next("4korp"), &pool)
+    .await?
+    .is_none());
+  Ok(())
+}
+
+#[rstest]
+#[tokio::test]
+async fn group_history(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  SnapshotEntry::insert(&snapshot(18, "214", "4korp/today"), &pool).await?;
+  SnapshotEntry::insert(&snapshot(19, "301", "4korp/today"), &pool).await?;
+
+  let history = SnapshotEntry::get_group_history("Ир1-21", &pool).await?;
+  let classrooms = history
+    .iter()
+    .map(|(date, group)| (date.day(), group.lectures().next().unwrap().classroom().unwrap()))
+    .collect::<Vec<_>>();
+  assert_eq!(classrooms, vec![(18, "214"), (19, "301")]);
+  Ok(())
+}
@@ -79,17 +79,36 @@ async fn get_all(#[future] pool: Pool) -> Result<()> {
   user1.config_mut().add_group("Ир3-21", &pool).await?;
   let mut user2 = User::get_by_id_or_create(1, &pool).await?;
   user2.config_mut().add_group("Ир3-21", &pool).await?;
+  user2.config_mut().add_campus("4korp", &pool).await?;
 
   let all = User::get_all_notified(&pool).await?;
 
   all
     .into_iter()
-    .zip([(0i64, vec!["Ир1-21".into(), "Ир3-21".into()]), (1i64, vec!["Ир3-21".into()])])
+    .zip([(0i64, vec!["Ир1-21".into(), "Ир3-21".into()], vec![]), (1i64, vec!["Ир3-21".into()], vec!["4korp".into()])])
     .for_each(|i| assert_eq!(i.0, i.1));
 
   Ok(())
 }
 
+#[rstest]
+#[tokio::test]
+async fn campuses(#[future] pool: Pool) -> Result<()> {
+  let pool = pool.await;
+  let mut user = User::get_by_id_or_create(0, &pool).await?;
+  assert!(user.config().has_campus("4korp"));
+
+  user.config_mut().add_campus("4korp", &pool).await?;
+  user.config_mut().add_campus("1korp", &pool).await?;
+  user.config_mut().remove_campus("1korp", &pool).await?;
+
+  let user = User::get_by_id_or_create(0, &pool).await?;
+  assert_eq!(user.config().campuses(), ["4korp"]);
+  assert!(user.config().has_campus("4korp"));
+  assert!(!user.config().has_campus("1korp"));
+  Ok(())
+}
+
 #[rstest]
 #[tokio::test]
 async fn update(#[future] pool: Pool) -> Result<()> {
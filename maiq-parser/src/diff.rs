@@ -0,0 +1,288 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::slice::Iter;
+
+use crate::snapshot::*;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct SnapshotDiff {
+  groups: Vec<GroupDiff>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct GroupDiff {
+  name: Box<str>,
+  kind: GroupDiffKind,
+  lectures: Vec<LectureDiff>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupDiffKind {
+  Added,
+  Removed,
+  Modified,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LectureDiff {
+  Added { lecture: Lecture },
+  Removed { lecture: Lecture },
+  Modified { before: Lecture, after: Lecture, fields: Vec<LectureField> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LectureField {
+  Order,
+  Name,
+  Classroom,
+  Teacher,
+  Subgroup,
+}
+
+impl SnapshotDiff {
+  /// Сравнивает два снапшота. Если предыдущего нет, все группы из `next` считаются добавленными
+  pub fn new(prev: Option<&Snapshot>, next: &Snapshot) -> Self {
+    let prev = match prev {
+      Some(prev) if prev.id() == next.id() => return Self::default(),
+      Some(prev) => prev,
+      None => return Self { groups: next.groups().map(GroupDiff::added).collect() },
+    };
+
+    let mut groups = next
+      .groups()
+      .filter_map(|group| match prev.group(group.name()) {
+        Some(prev_group) if prev_group.id() == group.id() => None,
+        Some(prev_group) => Some(GroupDiff::modified(prev_group, group)),
+        None => Some(GroupDiff::added(group)),
+      })
+      .collect::<Vec<GroupDiff>>();
+
+    groups.extend(
+      prev
+        .groups()
+        .filter(|group| next.group(group.name()).is_none())
+        .map(GroupDiff::removed),
+    );
+
+    groups.retain(|group| !group.lectures.is_empty() || group.kind != GroupDiffKind::Modified);
+    Self { groups }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.groups.is_empty()
+  }
+
+  pub fn groups(&self) -> Iter<'_, GroupDiff> {
+    self.groups.iter()
+  }
+
+  pub fn group(&self, name: &str) -> Option<&GroupDiff> {
+    self.groups.iter().find(|group| *group.name == *name)
+  }
+
+  pub fn has_group(&self, name: &str) -> bool {
+    self.group(name).is_some()
+  }
+}
+
+impl GroupDiff {
+  fn added(group: &Group) -> Self {
+    let lectures = group
+      .lectures()
+      .map(|lecture| LectureDiff::Added { lecture: lecture.clone() })
+      .collect();
+    Self { name: group.name().into(), kind: GroupDiffKind::Added, lectures }
+  }
+
+  fn removed(group: &Group) -> Self {
+    let lectures = group
+      .lectures()
+      .map(|lecture| LectureDiff::Removed { lecture: lecture.clone() })
+      .collect();
+    Self { name: group.name().into(), kind: GroupDiffKind::Removed, lectures }
+  }
+
+  /// Пары сопоставляются сначала по номеру и подгруппе, затем оставшиеся - по названию
+  fn modified(prev: &Group, next: &Group) -> Self {
+    let mut before = prev.lectures().collect::<Vec<&Lecture>>();
+    let mut after = next.lectures().collect::<Vec<&Lecture>>();
+    let mut lectures = vec![];
+
+    take_pairs(&mut before, &mut after, |lhs, rhs| lhs.id() == rhs.id());
+
+    for (lhs, rhs) in
+      take_pairs(&mut before, &mut after, |lhs, rhs| lhs.order() == rhs.order() && lhs.subgroup() == rhs.subgroup())
+        .into_iter()
+        .chain(take_pairs(&mut before, &mut after, |lhs, rhs| lhs.name() == rhs.name()))
+    {
+      lectures.push(LectureDiff::Modified { before: lhs.clone(), after: rhs.clone(), fields: changed_fields(lhs, rhs) });
+    }
+
+    lectures.extend(
+      before
+        .into_iter()
+        .map(|lecture| LectureDiff::Removed { lecture: lecture.clone() }),
+    );
+    lectures.extend(
+      after
+        .into_iter()
+        .map(|lecture| LectureDiff::Added { lecture: lecture.clone() }),
+    );
+    lectures.sort_by(|a, b| (a.lecture().order(), a.lecture().subgroup()).cmp(&(b.lecture().order(), b.lecture().subgroup())));
+
+    Self { name: next.name().into(), kind: GroupDiffKind::Modified, lectures }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn kind(&self) -> GroupDiffKind {
+    self.kind
+  }
+
+  pub fn lectures(&self) -> Iter<'_, LectureDiff> {
+    self.lectures.iter()
+  }
+}
+
+impl LectureDiff {
+  /// Актуальная пара: для удалённой - та, что была
+  pub fn lecture(&self) -> &Lecture {
+    match self {
+      Self::Added { lecture } | Self::Removed { lecture } => lecture,
+      Self::Modified { after, .. } => after,
+    }
+  }
+}
+
+/// Вынимает из `lhs` и `rhs` пары, удовлетворяющие `eq`
+fn take_pairs<'a, F: Fn(&Lecture, &Lecture) -> bool>(
+  lhs: &mut Vec<&'a Lecture>,
+  rhs: &mut Vec<&'a Lecture>,
+  eq: F,
+) -> Vec<(&'a Lecture, &'a Lecture)> {
+  let mut pairs = vec![];
+  let mut idx = 0;
+  while idx < lhs.len() {
+    match rhs.iter().position(|r| eq(lhs[idx], r)) {
+      Some(pos) => pairs.push((lhs.remove(idx), rhs.remove(pos))),
+      None => idx += 1,
+    }
+  }
+  pairs
+}
+
+fn changed_fields(lhs: &Lecture, rhs: &Lecture) -> Vec<LectureField> {
+  [
+    (LectureField::Order, lhs.order() != rhs.order()),
+    (LectureField::Name, lhs.name() != rhs.name()),
+    (LectureField::Classroom, lhs.classroom() != rhs.classroom()),
+    (LectureField::Teacher, lhs.teacher() != rhs.teacher()),
+    (LectureField::Subgroup, lhs.subgroup() != rhs.subgroup()),
+  ]
+  .into_iter()
+  .filter_map(|(field, changed)| changed.then_some(field))
+  .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::diff::*;
+  use maiq_time::*;
+
+  fn lecture(order: &str, name: &str, classroom: &str, subgroup: Option<&str>) -> Lecture {
+    Lecture::new(Some(order.into()), name.into(), Some(classroom.into()), subgroup.map(Into::into), None)
+  }
+
+  fn snapshot(groups: Vec<Group>) -> Snapshot {
+    Snapshot::new(DateTime::now_date(), groups)
+  }
+
+  #[rstest]
+  fn same_snapshot() {
+    let s = snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None)])]);
+    assert!(SnapshotDiff::new(Some(&s), &s).is_empty())
+  }
+
+  #[rstest]
+  fn no_previous() {
+    let s = snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None)])]);
+    let diff = SnapshotDiff::new(None, &s);
+    assert_eq!(diff.group("Group1").map(|g| g.kind()), Some(GroupDiffKind::Added));
+  }
+
+  #[rstest]
+  fn moved_classroom() {
+    let s1 = snapshot(vec![Group::new("Group1", vec![lecture("3", "Lecture1", "214", None)])]);
+    let s2 = snapshot(vec![Group::new("Group1", vec![lecture("3", "Lecture1", "301", None)])]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    let group = diff.group("Group1").unwrap();
+    assert_eq!(group.kind(), GroupDiffKind::Modified);
+    assert!(matches!(
+      group.lectures().next(),
+      Some(LectureDiff::Modified { before, after, fields })
+        if before.classroom() == Some("214") && after.classroom() == Some("301") && fields == &[LectureField::Classroom]
+    ));
+  }
+
+  #[rstest]
+  fn moved_order() {
+    let s1 = snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None)])]);
+    let s2 = snapshot(vec![Group::new("Group1", vec![lecture("2", "Lecture1", "214", None)])]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    assert!(matches!(
+      diff.group("Group1").unwrap().lectures().next(),
+      Some(LectureDiff::Modified { fields, .. }) if fields == &[LectureField::Order]
+    ));
+  }
+
+  #[rstest]
+  fn subgroups_matched_separately() {
+    let s1 = snapshot(vec![Group::new(
+      "Group1",
+      vec![lecture("1", "Lecture1", "214", Some("1")), lecture("1", "Lecture2", "301", Some("2"))],
+    )]);
+    let s2 = snapshot(vec![Group::new(
+      "Group1",
+      vec![lecture("1", "Lecture1", "214", Some("1")), lecture("1", "Lecture3", "301", Some("2"))],
+    )]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    let lectures = diff.group("Group1").unwrap().lectures().collect::<Vec<_>>();
+    assert_eq!(lectures.len(), 1);
+    assert!(matches!(lectures[0], LectureDiff::Modified { after, fields, .. }
+      if after.subgroup() == Some("2") && fields == &[LectureField::Name]));
+  }
+
+  #[rstest]
+  fn added_and_removed_groups() {
+    let s1 = snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None)])]);
+    let s2 = snapshot(vec![Group::new("Group2", vec![lecture("1", "Lecture1", "214", None)])]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    assert_eq!(diff.group("Group1").map(|g| g.kind()), Some(GroupDiffKind::Removed));
+    assert_eq!(diff.group("Group2").map(|g| g.kind()), Some(GroupDiffKind::Added));
+  }
+
+  #[rstest]
+  fn added_lecture() {
+    let s1 = snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None)])]);
+    let s2 =
+      snapshot(vec![Group::new("Group1", vec![lecture("1", "Lecture1", "214", None), lecture("2", "Lecture2", "301", None)])]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    let lectures = diff.group("Group1").unwrap().lectures().collect::<Vec<_>>();
+    assert!(matches!(lectures[..], [LectureDiff::Added { lecture }] if lecture.name() == "Lecture2"));
+  }
+
+  #[rstest]
+  fn serializable() {
+    let s1 = snapshot(vec![Group::new("Group1", vec![lecture("3", "Lecture1", "214", None)])]);
+    let s2 = snapshot(vec![Group::new("Group1", vec![lecture("3", "Lecture1", "301", None)])]);
+    let diff = SnapshotDiff::new(Some(&s1), &s2);
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(serde_json::from_str::<SnapshotDiff>(&json).unwrap(), diff);
+  }
+}
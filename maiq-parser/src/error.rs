@@ -1,16 +1,174 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Serialize;
 use thiserror::Error;
+use url::Url;
+
+const EXCERPT_LEN: usize = 200;
 
 #[derive(Error, Debug)]
 pub enum Error {
-  #[error("network: {0}")]
-  Network(#[from] Box<ureq::Error>),
+  #[error("network error while fetching {url}: {source}")]
+  Network { url: String, source: reqwest::Error },
+
+  #[error("timeout while fetching {url}")]
+  Timeout { url: String },
+
+  #[error("{url} responded with {status}: `{excerpt}`")]
+  HttpStatus { url: String, status: u16, excerpt: String },
+
+  #[error("unable to read {url}: {source}")]
+  Read { url: String, source: std::io::Error },
+
+  #[error("{url} is not valid {encoding}: `{excerpt}`")]
+  Encoding { url: String, encoding: &'static str, excerpt: String },
+
+  #[error("no table in html document {url}: `{excerpt}`")]
+  NoHtmlTable { url: String, excerpt: String },
+
+  #[error("unexpected table in {url} ({reason}): `{excerpt}`")]
+  UnexpectedShape { url: String, reason: String, excerpt: String },
+
+  #[error("unable to parse date in {url} ({error}): `{excerpt}`")]
+  Date {
+    url: String,
+    #[source]
+    error: DateError,
+    excerpt: String,
+  },
+
+  #[error("{url} is not modified, but there is nothing cached")]
+  NotModified { url: String },
+
+  #[error("unable to read file {path}: {source}")]
+  File { path: String, source: std::io::Error },
 
-  #[error("no table in html document")]
-  NoHtmlTable,
+  #[error("timetable: {0}")]
+  TimetableToml(toml::de::Error),
+
+  #[error("timetable: {0}")]
+  TimetableJson(serde_json::Error),
+
+  #[error("schedule: {0}")]
+  ScheduleToml(toml::de::Error),
+
+  #[error("layout: {0}")]
+  LayoutToml(toml::de::Error),
 }
 
 impl Error {
+  /// Обычная ситуация, о которой не нужно никому сообщать: например, на странице пока нет таблицы
   pub fn can_be_skipped(&self) -> bool {
-    matches!(self, Self::NoHtmlTable)
+    matches!(self, Self::NoHtmlTable { .. })
+  }
+
+  /// Стоит ли повторить запрос: таймаут, нет соединения, обрыв при чтении тела, 5xx или 429. Ошибки построения
+  /// запроса и редиректов повтор не исправит
+  pub fn is_transient(&self) -> bool {
+    match self {
+      Self::Timeout { .. } => true,
+      Self::Network { source, .. } => source.is_connect() || source.is_timeout() || source.is_body(),
+      Self::HttpStatus { status, .. } => *status >= 500 || *status == 429,
+      _ => false,
+    }
+  }
+
+  /// Страница изменилась так, что парсер её не понимает - на такое стоит посмотреть человеку
+  pub fn is_unexpected_content(&self) -> bool {
+    matches!(self, Self::Encoding { .. } | Self::UnexpectedShape { .. } | Self::Date { .. })
+  }
+
+  pub fn url(&self) -> Option<&str> {
+    match self {
+      Self::Network { url, .. }
+      | Self::Timeout { url }
+      | Self::HttpStatus { url, .. }
+      | Self::Read { url, .. }
+      | Self::Encoding { url, .. }
+      | Self::NoHtmlTable { url, .. }
+      | Self::UnexpectedShape { url, .. }
+      | Self::Date { url, .. }
+      | Self::NotModified { url } => Some(url.as_str()),
+      Self::File { .. } | Self::TimetableToml(_) | Self::TimetableJson(_) | Self::ScheduleToml(_) | Self::LayoutToml(_) => None,
+    }
+  }
+
+  pub fn excerpt(&self) -> Option<&str> {
+    match self {
+      Self::HttpStatus { excerpt, .. }
+      | Self::Encoding { excerpt, .. }
+      | Self::NoHtmlTable { excerpt, .. }
+      | Self::UnexpectedShape { excerpt, .. }
+      | Self::Date { excerpt, .. } => Some(excerpt),
+      _ => None,
+    }
+  }
+
+  pub(crate) fn file(path: &Path, source: std::io::Error) -> Self {
+    Self::File { path: path.display().to_string(), source }
+  }
+
+  pub(crate) fn network(url: &Url, source: reqwest::Error) -> Self {
+    match source.is_timeout() {
+      true => Self::Timeout { url: url.to_string() },
+      false => Self::Network { url: url.to_string(), source },
+    }
+  }
+}
+
+/// Начало `content` без html-тегов и лишних пробелов, не длиннее [`EXCERPT_LEN`] символов
+pub fn excerpt(content: &str) -> String {
+  let mut text = String::new();
+  let mut in_tag = false;
+  for c in content.chars() {
+    match c {
+      '<' => in_tag = true,
+      '>' if in_tag => {
+        in_tag = false;
+        text.push(' ');
+      }
+      _ if in_tag => (),
+      c => text.push(c),
+    }
+  }
+
+  let mut excerpt = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+  if let Some((idx, _)) = excerpt.char_indices().nth(EXCERPT_LEN) {
+    excerpt.truncate(idx);
+    excerpt.push('…');
+  }
+  excerpt
+}
+
+/// Почему не удалось достать дату из заголовка таблицы
+#[derive(Error, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateError {
+  #[error("no date in header `{0}`")]
+  NotFound(String),
+
+  #[error("invalid date {day:02}.{month:02}.{year}")]
+  InvalidDate { day: u32, month: u32, year: i32 },
+
+  #[error("{date} is not {weekday}")]
+  WeekdayMismatch { date: chrono::NaiveDate, weekday: chrono::Weekday },
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  #[case("<table><tr><td>Изменения</td>\n  <td>на 18 октября</td></tr></table>", "Изменения на 18 октября")]
+  #[case("  plain   text ", "plain text")]
+  fn excerpt_strips_tags(#[case] content: &str, #[case] expected: &str) {
+    assert_eq!(excerpt(content), expected);
+  }
+
+  #[rstest]
+  fn excerpt_is_short() {
+    let excerpt = excerpt(&"я".repeat(1000));
+    assert_eq!(excerpt.chars().count(), EXCERPT_LEN + 1);
   }
 }
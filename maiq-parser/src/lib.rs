@@ -1,3 +1,4 @@
+pub mod diff;
 pub mod error;
 pub mod parser;
 pub mod prelude;
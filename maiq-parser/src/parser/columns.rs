@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::table::Grid;
+
+/// Что лежит в столбце таблицы изменений
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+  Group,
+  Order,
+  Lecture,
+  Teacher,
+  Classroom,
+  Subgroup,
+}
+
+/// Начала заголовков столбцов в нижнем регистре. Порядок важен: `подгруппа` проверяется раньше `группа`
+const HEADERS: [(Column, &[&str]); 6] = [
+  (Column::Subgroup, &["подгруппа", "п/г"]),
+  (Column::Group, &["группа", "гр."]),
+  (Column::Order, &["пара", "№ пары", "номер пары", "урок"]),
+  (Column::Lecture, &["дисциплина", "предмет", "занятие", "мдк"]),
+  (Column::Teacher, &["преподаватель", "фио преподавателя", "учитель"]),
+  (Column::Classroom, &["аудитория", "ауд", "кабинет", "каб"]),
+];
+
+/// Без этих столбцов строку нельзя считать заголовком
+const REQUIRED: [Column; 3] = [Column::Group, Column::Order, Column::Lecture];
+
+/// Сколько строк от начала таблицы просматривается в поисках заголовка
+const HEADER_SEARCH_ROWS: usize = 4;
+
+/// Какой столбец сетки за что отвечает. Строится по строке заголовка, так что порядок столбцов и лишние столбцы,
+/// например, отдельный столбец с преподавателем, не ломают разбор
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ColumnMap {
+  columns: Vec<(Column, usize)>,
+}
+
+impl ColumnMap {
+  /// Старая раскладка страниц: группа, пара, дисциплина с преподавателем через запятую, аудитория
+  pub fn legacy() -> Self {
+    Self::default()
+      .with(Column::Group, 0)
+      .with(Column::Order, 1)
+      .with(Column::Lecture, 2)
+      .with(Column::Classroom, 3)
+  }
+
+  /// Столбцы по порядку, без заголовка. `None`, если столбец повторяется или нет группы, пары или дисциплины
+  pub fn positional(columns: &[Column]) -> Option<Self> {
+    let mut map = Self::default();
+    for (idx, column) in columns.iter().enumerate() {
+      if map.has(*column) {
+        return None;
+      }
+      map = map.with(*column, idx);
+    }
+    REQUIRED.iter().all(|column| map.has(*column)).then_some(map)
+  }
+
+  pub fn with(mut self, column: Column, idx: usize) -> Self {
+    self.columns.retain(|(known, _)| *known != column);
+    self.columns.push((column, idx));
+    self
+  }
+
+  /// Сопоставляет заголовки с известными названиями, см. [`HEADERS`]. Если столбец встречается дважды, берётся первый.
+  /// `None`, если не нашлось группы, пары или дисциплины
+  pub fn detect<S: AsRef<str>>(header: &[S]) -> Option<Self> {
+    Self::detect_with(header, &HashMap::new())
+  }
+
+  /// Как [`ColumnMap::detect`], но сначала проверяются дополнительные названия, например, из конфига раскладки
+  pub fn detect_with<S: AsRef<str>>(header: &[S], titles: &HashMap<Column, Vec<String>>) -> Option<Self> {
+    let mut map = Self::default();
+    for (idx, title) in header.iter().enumerate() {
+      match column_by_title(title.as_ref(), titles) {
+        Some(column) if map.get(column).is_none() => map = map.with(column, idx),
+        _ => (),
+      }
+    }
+    REQUIRED
+      .iter()
+      .all(|column| map.get(*column).is_some())
+      .then_some(map)
+  }
+
+  /// Первая строка сетки из первых [`HEADER_SEARCH_ROWS`], похожая на заголовок, и её раскладка
+  pub fn find_header(grid: &Grid) -> Option<(usize, Self)> {
+    Self::find_header_with(grid, &HashMap::new())
+  }
+
+  pub fn find_header_with(grid: &Grid, titles: &HashMap<Column, Vec<String>>) -> Option<(usize, Self)> {
+    (0..grid.height().min(HEADER_SEARCH_ROWS)).find_map(|row| {
+      let header = (0..grid.width())
+        .map(|col| grid.column_text(row, col))
+        .collect::<Vec<&str>>();
+      Self::detect_with(&header, titles).map(|map| (row, map))
+    })
+  }
+
+  pub fn get(&self, column: Column) -> Option<usize> {
+    self
+      .columns
+      .iter()
+      .find(|(known, _)| *known == column)
+      .map(|(_, idx)| *idx)
+  }
+
+  pub fn has(&self, column: Column) -> bool {
+    self.get(column).is_some()
+  }
+}
+
+fn column_by_title(title: &str, titles: &HashMap<Column, Vec<String>>) -> Option<Column> {
+  let title = title.trim().to_lowercase();
+  if title.is_empty() {
+    return None;
+  }
+
+  let starts_with = |prefix: &str| !prefix.trim().is_empty() && title.starts_with(&prefix.trim().to_lowercase());
+  titles
+    .iter()
+    .find(|(_, prefixes)| prefixes.iter().any(|prefix| starts_with(prefix)))
+    .map(|(column, _)| *column)
+    .or_else(|| {
+      HEADERS
+        .iter()
+        .find(|(_, prefixes)| prefixes.iter().any(|prefix| starts_with(prefix)))
+        .map(|(column, _)| *column)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  #[case(&["Группа", "Пара", "Дисциплина", "Аудитория"], Some(ColumnMap::legacy()))]
+  #[case(&["Пара", "Группа", "Ауд.", "Дисциплина"], Some(ColumnMap::default().with(Column::Order, 0).with(Column::Group, 1).with(Column::Classroom, 2).with(Column::Lecture, 3)))]
+  #[case(&["Группа", "Подгруппа", "№ пары", "Предмет", "Преподаватель", "Кабинет"], Some(ColumnMap::default().with(Column::Group, 0).with(Column::Subgroup, 1).with(Column::Order, 2).with(Column::Lecture, 3).with(Column::Teacher, 4).with(Column::Classroom, 5)))]
+  #[case(&["Группа", "", "Пара", "Дисциплина", "Дисциплина"], Some(ColumnMap::default().with(Column::Group, 0).with(Column::Order, 2).with(Column::Lecture, 3)))]
+  #[case(&["Группа", "Дисциплина", "Аудитория"], None)]
+  #[case(&["Изменения в расписании на 18 октября"], None)]
+  fn detect(#[case] header: &[&str], #[case] expected: Option<ColumnMap>) {
+    assert_eq!(ColumnMap::detect(header), expected);
+  }
+
+  #[rstest]
+  fn extra_titles() {
+    let header = ["Группа", "Пара", "Занятие", "ФИО", "Место"];
+    let titles = HashMap::from([(Column::Teacher, vec!["фио".into()]), (Column::Classroom, vec!["Место".into()])]);
+    let expected = ColumnMap::default()
+      .with(Column::Group, 0)
+      .with(Column::Order, 1)
+      .with(Column::Lecture, 2)
+      .with(Column::Teacher, 3)
+      .with(Column::Classroom, 4);
+    assert_eq!(ColumnMap::detect_with(&header, &titles), Some(expected));
+    assert_eq!(
+      ColumnMap::detect(&header),
+      Some(
+        ColumnMap::default()
+          .with(Column::Group, 0)
+          .with(Column::Order, 1)
+          .with(Column::Lecture, 2)
+      )
+    );
+  }
+}
@@ -0,0 +1,60 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+/// Управление запущенным [`super::repeating::RepeatingSnapshotParser`]. Дешево клонируется
+#[derive(Clone, Default, Debug)]
+pub struct ParserHandle {
+  refresh: Arc<Notify>,
+  paused: Arc<AtomicBool>,
+  token: CancellationToken,
+}
+
+impl ParserHandle {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Остановка по внешнему токену, например, общему для всего приложения
+  pub fn with_token(token: CancellationToken) -> Self {
+    Self { token, ..Default::default() }
+  }
+
+  /// Загрузить страницы сейчас, не дожидаясь тика. Работает и на паузе, и вне рабочих часов
+  pub fn refresh_now(&self) {
+    self.refresh.notify_one();
+  }
+
+  /// Тики пропускаются до [`ParserHandle::resume`]
+  pub fn pause(&self) {
+    self.paused.store(true, Ordering::Relaxed);
+  }
+
+  pub fn resume(&self) {
+    self.paused.store(false, Ordering::Relaxed);
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused.load(Ordering::Relaxed)
+  }
+
+  /// Цикл завершится, не начиная следующий тик. Начатая загрузка прерывается
+  pub fn shutdown(&self) {
+    self.token.cancel();
+  }
+
+  pub fn is_shutdown(&self) -> bool {
+    self.token.is_cancelled()
+  }
+
+  pub fn token(&self) -> &CancellationToken {
+    &self.token
+  }
+
+  pub(crate) async fn refreshed(&self) {
+    self.refresh.notified().await
+  }
+}
@@ -1,44 +1,164 @@
 use std::ops::Deref;
+use std::path::Path;
 use std::slice::Iter;
 
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::snapshot::Lecture;
+use crate::Error;
+use maiq_time::Weekday;
 
+/// Основное (недельное) расписание. Для групп, которые есть на странице с изменениями, страница накладывается поверх
+/// него, так что незаменённые пары этого дня всё равно попадают в снапшот.
+///
+/// В TOML выглядит так:
+/// ```toml
+/// [[groups]]
+/// name = "Ир3-21"
+///
+/// [[groups.lectures]]
+/// weekday = "mon"
+/// week = "even"
+/// order = "1"
+/// name = "Информационные технологии"
+/// classroom = "214"
+/// teacher = "Иванов И.Л."
+/// ```
+/// `week` можно не указывать - тогда пара стоит каждую неделю. Чётность - по номеру недели ISO 8601, с единицы.
+/// Пара без `weekday` (так выглядел старый JSON-формат: массив групп без дней недели) только подставляется вместо
+/// "По расписанию" в любой день, но не дополняет страницу
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
-pub struct DefaultLectures(Vec<DefaultGroup>);
+#[serde(from = "RawDefaultLectures")]
+pub struct DefaultLectures {
+  groups: Vec<DefaultGroup>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDefaultLectures {
+  Groups {
+    #[serde(default)]
+    groups: Vec<DefaultGroup>,
+  },
+  Legacy(Vec<DefaultGroup>),
+}
+
+impl From<RawDefaultLectures> for DefaultLectures {
+  fn from(raw: RawDefaultLectures) -> Self {
+    match raw {
+      RawDefaultLectures::Groups { groups } | RawDefaultLectures::Legacy(groups) => Self { groups },
+    }
+  }
+}
 
-#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
 pub enum LectureWeek {
+  #[serde(alias = "Even")]
   Even,
+  #[serde(alias = "Odd")]
   Odd,
   #[default]
+  #[serde(alias = "Every")]
   Every,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DefaultGroup {
   pub name: String,
+  #[serde(default)]
   pub lectures: Vec<DefaultLecture>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DefaultLecture {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub weekday: Option<Weekday>,
+  #[serde(default)]
   pub week: LectureWeek,
   #[serde(flatten)]
   inner: Lecture,
 }
 
 impl DefaultLectures {
-  pub fn group<S: AsRef<str>>(&self, name: S) -> Option<Iter<DefaultLecture>> {
+  pub fn new(groups: Vec<DefaultGroup>) -> Self {
+    Self { groups }
+  }
+
+  pub fn from_toml(raw: &str) -> Result<Self, Error> {
+    toml::from_str(raw).map_err(Error::TimetableToml)
+  }
+
+  pub fn from_json(raw: &str) -> Result<Self, Error> {
+    serde_json::from_str(raw).map_err(Error::TimetableJson)
+  }
+
+  /// Формат выбирается по расширению: `.json` - JSON, всё остальное - TOML
+  pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+    match path.extension() {
+      Some(ext) if ext == "json" => Self::from_json(&raw),
+      _ => Self::from_toml(&raw),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.groups.iter().all(|group| group.lectures.is_empty())
+  }
+
+  pub fn groups(&self) -> Iter<'_, DefaultGroup> {
+    self.groups.iter()
+  }
+
+  pub fn group<S: AsRef<str>>(&self, name: S) -> Option<Iter<'_, DefaultLecture>> {
     let name = name.as_ref();
     self
-      .0
+      .groups
       .iter()
       .find(|group| group.name == name)
       .map(|group| group.lectures.iter())
   }
+
+  /// Пары группы в этот день недели с учётом чётности недели, в том числе пары без дня недели
+  pub fn day<S: AsRef<str>>(&self, name: S, weekday: Weekday, is_week_even: bool) -> Vec<&DefaultLecture> {
+    self
+      .group(name)
+      .map(|lectures| {
+        lectures
+          .filter(|lecture| lecture.weekday.is_none_or(|day| day == weekday) && lecture.week.matches(is_week_even))
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+}
+
+impl LectureWeek {
+  pub fn matches(&self, is_week_even: bool) -> bool {
+    match self {
+      LectureWeek::Even => is_week_even,
+      LectureWeek::Odd => !is_week_even,
+      LectureWeek::Every => true,
+    }
+  }
+}
+
+impl DefaultLecture {
+  pub fn new(weekday: Weekday, week: LectureWeek, lecture: Lecture) -> Self {
+    Self { weekday: Some(weekday), week, inner: lecture }
+  }
+
+  /// Пара из основного расписания в снапшоте
+  pub fn to_lecture(&self) -> Lecture {
+    Lecture::new(
+      self.order().map(Into::into),
+      self.name().into(),
+      self.classroom().map(Into::into),
+      self.subgroup().map(Into::into),
+      self.teacher().map(Into::into),
+    )
+  }
 }
 
 impl Deref for DefaultLecture {
@@ -48,3 +168,68 @@ impl Deref for DefaultLecture {
     &self.inner
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TIMETABLE: &str = r#"
+    [[groups]]
+    name = "Ир3-21"
+
+    [[groups.lectures]]
+    weekday = "mon"
+    order = "1"
+    name = "Информационные технологии"
+    classroom = "214"
+
+    [[groups.lectures]]
+    weekday = "Monday"
+    week = "even"
+    order = "2"
+    name = "История"
+
+    [[groups.lectures]]
+    weekday = "mon"
+    week = "odd"
+    order = "2"
+    name = "Физкультура"
+  "#;
+
+  #[rstest]
+  #[case(true, vec!["Информационные технологии", "История"])]
+  #[case(false, vec!["Информационные технологии", "Физкультура"])]
+  fn day(#[case] is_week_even: bool, #[case] expected: Vec<&str>) {
+    let timetable = DefaultLectures::from_toml(TIMETABLE).unwrap();
+    let names = timetable
+      .day("Ир3-21", Weekday::Mon, is_week_even)
+      .into_iter()
+      .map(|lecture| lecture.name())
+      .collect::<Vec<&str>>();
+    assert_eq!(names, expected);
+    assert!(timetable.day("Ир3-21", Weekday::Tue, is_week_even).is_empty());
+  }
+
+  #[rstest]
+  fn json() {
+    let timetable = DefaultLectures::from_toml(TIMETABLE).unwrap();
+    let json = serde_json::to_string(&timetable).unwrap();
+    let timetable = DefaultLectures::from_json(&json).unwrap();
+    assert_eq!(timetable.group("Ир3-21").unwrap().len(), 3);
+  }
+
+  #[rstest]
+  fn legacy() {
+    let timetable =
+      DefaultLectures::from_json(r#"[{ "name": "Ир3-21", "lectures": [{ "week": "Odd", "name": "Физкультура" }] }]"#).unwrap();
+    let lectures = timetable.day("Ир3-21", Weekday::Wed, false);
+    assert_eq!(lectures.iter().map(|lecture| lecture.name()).collect::<Vec<_>>(), vec!["Физкультура"]);
+    assert_eq!(lectures[0].weekday, None);
+    assert!(timetable.day("Ир3-21", Weekday::Wed, true).is_empty());
+  }
+
+  #[rstest]
+  fn invalid() {
+    assert!(DefaultLectures::from_toml("[[groups]]\nname = 1").is_err());
+  }
+}
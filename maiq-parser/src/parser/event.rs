@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use crate::diff::SnapshotDiff;
+use crate::snapshot::Snapshot;
+use crate::Error;
+use maiq_time::DateTime;
+
+use super::report::ParseReport;
+use super::source::Source;
+
+/// Через сколько неудачных загрузок подряд источник считается недоступным
+pub const UNAVAILABLE_AFTER: u32 = 3;
+
+/// Событие [`super::repeating::RepeatingSnapshotParser`]. Подписчики получают их через `tokio::sync::broadcast`
+#[derive(Clone, Debug)]
+pub enum ParserEvent {
+  /// Начало очередного тика, до загрузки страниц
+  Tick { at: DateTime },
+  /// Со страницы получен новый снапшот. `diff` пустой, если в группах ничего не поменялось,
+  /// например, страница "на завтра" стала страницей "на сегодня"
+  SnapshotChanged { source: Source, snapshot: Snapshot, diff: SnapshotDiff, report: ParseReport },
+  /// Страницу не удалось загрузить или разобрать. `failures` - сколько раз подряд
+  FetchFailed { source: Source, error: Arc<Error>, failures: u32 },
+  /// Источник не отвечает [`UNAVAILABLE_AFTER`] раз подряд. Приходит один раз до восстановления
+  SourceUnavailable { source: Source, error: Arc<Error> },
+  /// Страница снова загружается после `failures` неудач подряд
+  Recovered { source: Source, failures: u32 },
+}
+
+impl ParserEvent {
+  pub fn source(&self) -> Option<&Source> {
+    match self {
+      ParserEvent::Tick { .. } => None,
+      ParserEvent::SnapshotChanged { source, .. }
+      | ParserEvent::FetchFailed { source, .. }
+      | ParserEvent::SourceUnavailable { source, .. }
+      | ParserEvent::Recovered { source, .. } => Some(source),
+    }
+  }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::ETAG;
+use reqwest::header::IF_MODIFIED_SINCE;
+use reqwest::header::IF_NONE_MATCH;
+use reqwest::header::LAST_MODIFIED;
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::error::excerpt;
+use crate::Error;
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<FetchResponse, Error>> + Send + 'a>>;
+
+/// Источник сырых страниц с расписанием
+pub trait Fetcher: Debug + Send + Sync {
+  /// Если переданы `validators`, источник может ответить [`FetchResponse::NotModified`]
+  fn fetch<'a>(&'a self, url: &'a Url, validators: Option<&'a Validators>) -> FetchFuture<'a>;
+}
+
+impl<F: Fetcher + ?Sized> Fetcher for Arc<F> {
+  fn fetch<'a>(&'a self, url: &'a Url, validators: Option<&'a Validators>) -> FetchFuture<'a> {
+    (**self).fetch(url, validators)
+  }
+}
+
+/// `ETag` и `Last-Modified` прошлого ответа - для условного запроса
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Validators {
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum FetchResponse {
+  Page { body: Vec<u8>, validators: Validators },
+  NotModified,
+}
+
+/// Загружает страницы по http. Таймауты, обрывы соединения, 5xx и 429 повторяются
+/// с экспоненциальной задержкой: `backoff`, `2 * backoff`, `4 * backoff`...
+#[derive(Clone, Debug)]
+pub struct HttpFetcher {
+  client: reqwest::Client,
+  retries: u32,
+  backoff: Duration,
+}
+
+/// Читает сохранённые страницы с диска. Url `https://host/path/page.htm` читается из `<root>/host/path/page.htm` -
+/// так же, как их раскладывает `wget -x`. Url со схемой `file` читаются напрямую
+#[derive(Clone, Debug)]
+pub struct FsFetcher {
+  root: PathBuf,
+}
+
+/// Отдаёт заранее положенные в память страницы, в основном для тестов
+#[derive(Default, Debug)]
+pub struct MemoryFetcher {
+  pages: Mutex<HashMap<Url, Vec<u8>>>,
+}
+
+impl HttpFetcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_timeout(timeout: Duration) -> Self {
+    let client = reqwest::Client::builder()
+      .timeout(timeout)
+      .build()
+      .expect("unable to build http client");
+    Self { client, retries: 0, backoff: Duration::ZERO }
+  }
+
+  /// `retries` - сколько раз повторить запрос после первой неудачи. По умолчанию 3 раза, начиная с 2 секунд
+  pub fn with_retries(self, retries: u32, backoff: Duration) -> Self {
+    Self { retries, backoff, ..self }
+  }
+
+  async fn try_fetch(&self, url: &Url, validators: Option<&Validators>) -> Result<FetchResponse, Error> {
+    let mut request = self.client.get(url.clone());
+    if let Some(validators) = validators {
+      if let Some(etag) = validators.etag.as_ref() {
+        request = request.header(IF_NONE_MATCH, etag);
+      }
+      if let Some(last_modified) = validators.last_modified.as_ref() {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+      }
+    }
+
+    let response = request.send().await.map_err(|err| Error::network(url, err))?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+      return Ok(FetchResponse::NotModified);
+    }
+
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+      let body = response.text().await.unwrap_or_default();
+      return Err(Error::HttpStatus { url: url.to_string(), status: status.as_u16(), excerpt: excerpt(&body) });
+    }
+    let header = |name| {
+      response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+    };
+    let validators = Validators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) };
+    let body = response
+      .bytes()
+      .await
+      .map_err(|err| Error::network(url, err))?
+      .to_vec();
+    Ok(FetchResponse::Page { body, validators })
+  }
+}
+
+impl Default for HttpFetcher {
+  fn default() -> Self {
+    Self::with_timeout(Duration::from_secs(15)).with_retries(3, Duration::from_secs(2))
+  }
+}
+
+impl Fetcher for HttpFetcher {
+  fn fetch<'a>(&'a self, url: &'a Url, validators: Option<&'a Validators>) -> FetchFuture<'a> {
+    Box::pin(async move {
+      let mut attempt = 0;
+      loop {
+        match self.try_fetch(url, validators).await {
+          Err(err) if err.is_transient() && attempt < self.retries => {
+            let delay = self.backoff * 2u32.pow(attempt);
+            warn!(target: "parser", "unable to fetch {}: {}; retrying in {:?}", url, err, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+          }
+          result => return result,
+        }
+      }
+    })
+  }
+}
+
+impl FetchResponse {
+  fn page(body: Vec<u8>) -> Self {
+    Self::Page { body, validators: Validators::default() }
+  }
+}
+
+impl FsFetcher {
+  pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+    Self { root: root.into() }
+  }
+
+  fn path(&self, url: &Url) -> PathBuf {
+    if url.scheme() == "file" {
+      if let Ok(path) = url.to_file_path() {
+        return path;
+      }
+    }
+
+    let mut path = self.root.join(url.host_str().unwrap_or_default());
+    path.extend(url.path_segments().into_iter().flatten());
+    path
+  }
+}
+
+impl Fetcher for FsFetcher {
+  fn fetch<'a>(&'a self, url: &'a Url, _: Option<&'a Validators>) -> FetchFuture<'a> {
+    Box::pin(async move {
+      match tokio::fs::read(self.path(url)).await {
+        Ok(body) => Ok(FetchResponse::page(body)),
+        Err(source) => Err(Error::Read { url: url.to_string(), source }),
+      }
+    })
+  }
+}
+
+impl MemoryFetcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_page<U: AsRef<str>, B: Into<Vec<u8>>>(self, url: U, page: B) -> Result<Self, url::ParseError> {
+    self.set_page(url, page)?;
+    Ok(self)
+  }
+
+  pub fn set_page<U: AsRef<str>, B: Into<Vec<u8>>>(&self, url: U, page: B) -> Result<(), url::ParseError> {
+    let url = url.as_ref().parse()?;
+    self.pages.lock().unwrap().insert(url, page.into());
+    Ok(())
+  }
+
+  pub fn remove_page<U: AsRef<str>>(&self, url: U) -> Result<(), url::ParseError> {
+    let url = url.as_ref().parse()?;
+    self.pages.lock().unwrap().remove(&url);
+    Ok(())
+  }
+}
+
+impl Fetcher for MemoryFetcher {
+  fn fetch<'a>(&'a self, url: &'a Url, _: Option<&'a Validators>) -> FetchFuture<'a> {
+    let page = self.pages.lock().unwrap().get(url).cloned();
+    Box::pin(async move {
+      page.map(FetchResponse::page).ok_or_else(|| Error::Read {
+        url: url.to_string(),
+        source: std::io::Error::new(std::io::ErrorKind::NotFound, format!("no page for {}", url)),
+      })
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+  use std::io::Write;
+  use std::time::Instant;
+
+  use super::*;
+  use crate::parser::impls::SnapshotParser4;
+  use crate::parser::report::ParseReport;
+  use crate::parser::source::*;
+  use crate::parser::SnapshotParserBuilder;
+  use crate::snapshot::Id;
+
+  /// Отвечает по очереди заготовленными ответами, по соединению на запрос, и запоминает заголовки запросов
+  struct TestServer {
+    url: Url,
+    requests: Arc<Mutex<Vec<String>>>,
+  }
+
+  impl TestServer {
+    fn start(responses: Vec<String>) -> Self {
+      let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+      let url = format!("http://{}/today.htm", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+      let requests = Arc::new(Mutex::new(vec![]));
+      let seen = requests.clone();
+      std::thread::spawn(move || {
+        for (response, stream) in responses.into_iter().zip(listener.incoming()) {
+          let mut stream = stream.unwrap();
+          let mut request = vec![];
+          let mut buf = [0; 1024];
+          while !request.ends_with(b"\r\n\r\n") {
+            match stream.read(&mut buf).unwrap() {
+              0 => break,
+              n => request.extend_from_slice(&buf[..n]),
+            }
+          }
+          seen
+            .lock()
+            .unwrap()
+            .push(String::from_utf8_lossy(&request).to_lowercase());
+          stream.write_all(response.as_bytes()).unwrap();
+        }
+      });
+      Self { url, requests }
+    }
+
+    fn requests(&self) -> Vec<String> {
+      self.requests.lock().unwrap().clone()
+    }
+  }
+
+  fn response(status: &str, headers: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\n{}content-length: {}\r\nconnection: close\r\n\r\n{}", status, headers, body.len(), body)
+  }
+
+  fn unavailable() -> String {
+    response("503 Service Unavailable", "", "busy")
+  }
+
+  fn page(body: &str) -> String {
+    response("200 OK", "etag: \"1\"\r\nlast-modified: Wed, 18 Oct 2023 07:00:00 GMT\r\n", body)
+  }
+
+  fn not_modified() -> String {
+    response("304 Not Modified", "", "")
+  }
+
+  #[rstest]
+  #[case("https://rsp.chemk.org/4korp/today.htm", "/tmp/pages/rsp.chemk.org/4korp/today.htm")]
+  #[case("file:///var/today.htm", "/var/today.htm")]
+  fn fs_path(#[case] url: &str, #[case] expected: &str) {
+    let fetcher = FsFetcher::new("/tmp/pages");
+    assert_eq!(fetcher.path(&url.parse().unwrap()), PathBuf::from(expected));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn memory() {
+    let fetcher = MemoryFetcher::new()
+      .with_page("https://example.com/today.htm", "page")
+      .unwrap();
+    let url = "https://example.com/today.htm".parse().unwrap();
+    assert!(matches!(fetcher.fetch(&url, None).await.unwrap(), FetchResponse::Page { body, .. } if body == b"page"));
+
+    fetcher.remove_page("https://example.com/today.htm").unwrap();
+    assert!(fetcher.fetch(&url, None).await.is_err());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn http_retries_with_backoff() {
+    let server = TestServer::start(vec![unavailable(), unavailable(), page("page")]);
+    let fetcher = HttpFetcher::with_timeout(Duration::from_secs(5)).with_retries(2, Duration::from_millis(50));
+    let started = Instant::now();
+    let response = fetcher.fetch(&server.url, None).await.unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(150), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 3);
+    let etag = Some("\"1\"".into());
+    assert!(matches!(response, FetchResponse::Page { body, validators } if body == b"page" && validators.etag == etag));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn http_stops_after_retries() {
+    let server = TestServer::start(vec![unavailable(), unavailable(), unavailable(), page("page")]);
+    let fetcher = HttpFetcher::with_timeout(Duration::from_secs(5)).with_retries(2, Duration::from_millis(1));
+    let err = fetcher.fetch(&server.url, None).await.unwrap_err();
+
+    assert!(matches!(err, Error::HttpStatus { status: 503, ref excerpt, .. } if excerpt == "busy"));
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn http_conditional_request() {
+    let server = TestServer::start(vec![not_modified()]);
+    let fetcher = HttpFetcher::with_timeout(Duration::from_secs(5));
+    let validators = Validators { etag: Some("\"1\"".into()), last_modified: Some("Wed, 18 Oct 2023 07:00:00 GMT".into()) };
+    let response = fetcher.fetch(&server.url, Some(&validators)).await.unwrap();
+
+    assert!(matches!(response, FetchResponse::NotModified));
+    let request = &server.requests()[0];
+    assert!(request.contains("if-none-match: \"1\"\r\n"), "{}", request);
+    assert!(request.contains("if-modified-since: wed, 18 oct 2023 07:00:00 gmt\r\n"), "{}", request);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn http_not_modified_page_is_cached() {
+    const PAGE: &str = r#"
+      <table>
+        <tr><td>Изменения в расписании на 18 октября</td></tr>
+        <tr><td>Группа</td><td>Пара</td><td>Дисциплина</td><td>Аудитория</td></tr>
+        <tr><td>Ир3-21</td><td>1</td><td>Информационные технологии, Иванов И.Л.</td><td>214</td></tr>
+      </table>"#;
+    let server = TestServer::start(vec![page(PAGE), not_modified()]);
+    let mut parser = SnapshotParserBuilder::new()
+      .with_today_url(server.url.as_str())
+      .unwrap()
+      .with_groups(["Ир3-21"])
+      .with_fetcher(HttpFetcher::with_timeout(Duration::from_secs(5)))
+      .with_encoding(encoding_rs::UTF_8)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, ..) = parser.fetch_today().await.unwrap().unwrap();
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), snapshot.clone(), ParseReport::new());
+    let (cached, diff, _) = parser.fetch_today().await.unwrap().unwrap();
+
+    assert_eq!(cached.id(), snapshot.id());
+    assert!(diff.is_empty());
+    let requests = server.requests();
+    assert!(!requests[0].contains("if-none-match"));
+    assert!(requests[1].contains("if-none-match: \"1\"\r\n"), "{}", requests[1]);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn http_gives_up_after_retries() {
+    let fetcher = HttpFetcher::with_timeout(Duration::from_millis(200)).with_retries(2, Duration::from_millis(1));
+    // порт свободен: слушатель закрыт сразу после выбора
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let url = format!("http://127.0.0.1:{}/today.htm", port).parse().unwrap();
+    let err = fetcher.fetch(&url, None).await.unwrap_err();
+    assert!(err.is_transient());
+  }
+}
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use crate::parser::default_lectures::DefaultLectures;
+use crate::parser::layout::Layout;
+use crate::parser::report::ParseReport;
+use crate::parser::table::Table;
+use crate::parser::SnapshotParserAgent;
+use crate::snapshot::Snapshot;
+use maiq_time::DateTime;
+
+use super::SnapshotParser4;
+
+/// Агент с раскладкой из конфига, см. [`Layout`]. Таблицу разбирает так же, как [`SnapshotParser4`], но названия
+/// столбцов, номера пар, подгруппы, преподаватели и маркеры "Нет" и "По расписанию" берутся из описания.
+/// Без [`DeclarativeParser::with_layout`] ведёт себя в точности как [`SnapshotParser4`]
+pub struct DeclarativeParser(SnapshotParser4);
+
+impl DeclarativeParser {
+  pub fn with_layout(self, layout: Arc<Layout>) -> Self {
+    Self(self.0.with_layout(layout))
+  }
+}
+
+impl SnapshotParserAgent for DeclarativeParser {
+  const NAME: &'static str = "declarative";
+
+  /// Без раскладки это тот же [`SnapshotParser4`], так что сам по себе он таблицу не берёт. С раскладкой его регистрирует
+  /// [`crate::parser::registry::AgentRegistry::with_layout`], уверенность - по [`Layout::detect`]
+  fn detect(_: &Table, _: DateTime) -> f32 {
+    0.0
+  }
+
+  fn new(fallback_date: DateTime) -> Self {
+    Self(SnapshotParser4::new(fallback_date))
+  }
+
+  fn with_groups<S: AsRef<str>, I: Iterator<Item = S>>(self, group_names: I) -> Self {
+    Self(self.0.with_groups(group_names))
+  }
+
+  fn with_default_lectures(self, lectures: DefaultLectures) -> Self {
+    Self(self.0.with_default_lectures(lectures))
+  }
+
+  fn parse(self, table: Table) -> (Snapshot, ParseReport) {
+    self.0.parse(table)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use maiq_time::*;
+
+  const LAYOUT: &str = r#"
+    order = '^\d+$'
+    group = '^(?P<group>\S+)(?:\s*\((?P<subgroup>\d)\))?$'
+    teacher = '^(?P<name>.+?)\s+\[(?P<teacher>.+)\]$'
+    no_lecture = ["Отмена"]
+
+    [headers]
+    order = ["№"]
+    lecture = ["Что"]
+    classroom = ["Где"]
+  "#;
+
+  #[rstest]
+  fn custom_layout() {
+    let rows = vec![
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Группа", "№", "Что", "Где"],
+      vec!["Ир3-21 (2)", "1", "История [Волков К.К.]", "102"],
+      vec!["Ир3-21", "2", "Отмена", ""],
+      vec!["С1-22", "1", "Математика, Петрова А.А.", "105"],
+    ];
+    let reference = FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, 16, 9, 0, 0)
+      .unwrap();
+    let (snapshot, report) = DeclarativeParser::new(reference)
+      .with_layout(Arc::new(Layout::from_toml(LAYOUT).unwrap()))
+      .with_groups(["Ир3-21", "С1-22"].iter())
+      .parse(Table::from_text_rows(rows.clone()));
+
+    let lecture = snapshot.group("Ир3-21").unwrap().lectures().next().unwrap();
+    assert_eq!(
+      (lecture.order(), lecture.name(), lecture.subgroup(), lecture.teacher(), lecture.classroom()),
+      (Some("1"), "История", Some("2"), Some("Волков К.К."), Some("102"))
+    );
+    assert_eq!(snapshot.group("Ир3-21").unwrap().lectures().len(), 1);
+    let lecture = snapshot.group("С1-22").unwrap().lectures().next().unwrap();
+    assert_eq!((lecture.name(), lecture.teacher()), ("Математика, Петрова А.А.", None));
+    assert!(report.is_empty());
+
+    // без раскладки заголовок не распознаётся, а "Отмена" - обычная пара
+    let (snapshot, _) = DeclarativeParser::new(reference)
+      .with_groups(["Ир3-21", "С1-22"].iter())
+      .parse(Table::from_text_rows(rows));
+    assert_eq!(snapshot.group("Ир3-21").unwrap().lectures().len(), 2);
+  }
+
+  #[rstest]
+  fn positional_columns() {
+    let rows = vec![
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Ир3-21", "Волков К.К.", "1", "История", "102"],
+      vec!["", "Петрова А.А.", "2", "Математика", "105"],
+    ];
+    let layout = Layout::from_toml("columns = ['group', 'teacher', 'order', 'lecture', 'classroom']").unwrap();
+    let (snapshot, report) = DeclarativeParser::new(DateTime::now())
+      .with_layout(Arc::new(layout))
+      .with_groups(["Ир3-21"].iter())
+      .parse(Table::from_text_rows(rows));
+
+    let lectures = snapshot
+      .group("Ир3-21")
+      .unwrap()
+      .lectures()
+      .map(|lecture| (lecture.order().unwrap(), lecture.name(), lecture.teacher(), lecture.classroom()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      lectures,
+      vec![("1", "История", Some("Волков К.К."), Some("102")), ("2", "Математика", Some("Петрова А.А."), Some("105"))]
+    );
+    assert!(report.is_empty());
+  }
+}
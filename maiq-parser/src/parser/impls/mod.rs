@@ -1,2 +1,4 @@
+mod declarative;
 mod parser4;
+pub use declarative::*;
 pub use parser4::*;
@@ -1,12 +1,17 @@
 use std::iter::Peekable;
+use std::sync::Arc;
+use std::sync::OnceLock;
 
 use crate::parser::SnapshotParserAgent;
 
+use crate::parser::columns::*;
 use crate::parser::default_lectures::*;
+use crate::parser::layout::Layout;
 use crate::parser::parse_date::*;
+use crate::parser::report::*;
 use crate::parser::table::*;
 use crate::snapshot::*;
-use crate::utils::time::*;
+use maiq_time::*;
 
 macro_rules! empty_to_none {
   ($e: expr) => {
@@ -27,17 +32,38 @@ struct RawLecture {
   name: Option<Box<str>>,
   teacher: Option<Box<str>>,
   classroom: Option<Box<str>>,
+  cells: Vec<String>,
+}
+
+/// Пара, которую страница с изменениями заменила или отменила
+struct Override {
+  group_name: Box<str>,
+  order: Box<str>,
+  subgroup: Option<Box<str>>,
 }
 
 pub struct SnapshotParser4 {
   default_lectures: DefaultLectures,
   fallback_date: DateTime,
   group_names: Vec<Box<str>>,
+  layout: Arc<Layout>,
+}
+
+/// Агент создаётся на каждую таблицу, а выражения раскладки по умолчанию достаточно скомпилировать один раз
+fn default_layout() -> Arc<Layout> {
+  static LAYOUT: OnceLock<Arc<Layout>> = OnceLock::new();
+  LAYOUT.get_or_init(|| Arc::new(Layout::default())).clone()
 }
 
 impl SnapshotParserAgent for SnapshotParser4 {
+  const NAME: &'static str = "parser4";
+
+  fn detect(table: &Table, now: DateTime) -> f32 {
+    default_layout().detect(table, now)
+  }
+
   fn new(fallback_date: DateTime) -> Self {
-    Self { default_lectures: DefaultLectures::default(), fallback_date, group_names: vec![] }
+    Self { default_lectures: DefaultLectures::default(), fallback_date, group_names: vec![], layout: default_layout() }
   }
 
   fn with_default_lectures(self, lectures: DefaultLectures) -> Self {
@@ -51,55 +77,164 @@ impl SnapshotParserAgent for SnapshotParser4 {
     Self { group_names, ..self }
   }
 
-  fn parse(self, table: Table) -> Snapshot {
-    let mut rows = table.rows.into_iter();
-    let date = parse_date(&mut rows).unwrap_or(self.fallback_date);
-    let is_week_even = date.iso_week().week0() % 2 == 0;
-
-    let raw_lectures = self.parse_raw_lectures(rows.skip(1).peekable());
-    let mut groups = self.assign_to_groups(raw_lectures.into_iter(), is_week_even);
+  fn parse(self, table: Table) -> (Snapshot, ParseReport) {
+    let mut report = ParseReport::new();
+    let rows = table.text_rows();
+    let date_row = rows.first().cloned().unwrap_or_default();
+    let mut rows = rows.into_iter();
+    let date = match parse_date(&mut rows, self.fallback_date) {
+      Ok(date) => date,
+      Err(err) => {
+        warn!(target: "parser", "unable to parse date: {}; using fallback {}", err, self.fallback_date);
+        report.push(&date_row, ReportReason::FallbackDate { fallback: self.fallback_date.to_rfc3339(), error: err });
+        self.fallback_date
+      }
+    };
+    let grid = table.grid();
+    let raw_lectures = match (self.layout.find_header(&grid), self.layout.columns()) {
+      (Some((header, columns)), _) => self.parse_grid_lectures(&grid, header + 1, &columns),
+      (None, Some(columns)) => {
+        debug!(target: "parser", "no header row found; using columns from layout");
+        let first = first_group_row(&grid, columns, |name| self.is_group_name(name));
+        self.parse_grid_lectures(&grid, first, columns)
+      }
+      (None, None) => {
+        debug!(target: "parser", "no header row found; assuming group, order, lecture and classroom columns");
+        self.parse_raw_lectures(rows.skip(1).peekable())
+      }
+    };
+    let mut overrides = vec![];
+    let mut groups = self.assign_to_groups(raw_lectures.into_iter(), date, &mut overrides, &mut report);
+    self.merge_default_lectures(&mut groups, date, &overrides);
     groups.retain(|g| g.has_lectures());
-    Snapshot::new(date, groups)
+    (Snapshot::new(date, groups), report)
   }
 }
 
 impl SnapshotParser4 {
+  pub(crate) fn with_layout(self, layout: Arc<Layout>) -> Self {
+    Self { layout, ..self }
+  }
+
+  /// Строки начиная с `first`, столбцы - по [`ColumnMap`]. Объединённая по вертикали ячейка группы повторяется в каждой
+  /// накрытой строке, так что группа из предыдущих строк берётся, только если ячейка пустая
+  fn parse_grid_lectures(&self, grid: &Grid, first: usize, columns: &ColumnMap) -> Vec<RawLecture> {
+    let mut anchor = None;
+    (first..grid.height())
+      .filter_map(|row| {
+        let cells = grid.own_texts(row);
+        if cells.is_empty() {
+          return None;
+        }
+
+        let cells = cells.into_iter().map(Into::into).collect();
+        Some(RawLecture { cells, ..self.parse_grid_lecture(grid, row, columns, &mut anchor) })
+      })
+      .collect()
+  }
+
+  fn parse_grid_lecture(&self, grid: &Grid, row: usize, columns: &ColumnMap, anchor: &mut Option<Box<str>>) -> RawLecture {
+    let text = |column| {
+      columns
+        .get(column)
+        .map(|col| grid.column_text(row, col).trim())
+        .unwrap_or_default()
+    };
+
+    let (group_name, subgroup) = match text(Column::Group) {
+      "" => anchor
+        .as_deref()
+        .map(|anchor| self.layout.split_group(anchor))
+        .unwrap_or_default(),
+      group if self.is_group_name(group) => {
+        *anchor = Some(group.into());
+        self.layout.split_group(group)
+      }
+      // ячейку группы пропустили без rowspan, и остальные съехали влево - такую строку разбираем по-старому
+      _ => return self.parse_raw_lecture(grid.own_texts(row).into_iter().peekable(), anchor),
+    };
+    let subgroup = empty_to_none!(text(Column::Subgroup).split(' ').next()).or(subgroup);
+
+    // номер пары, растянутый на несколько строк, отмечается в отчёте так же, как пропущенный
+    let order_spanned = columns
+      .get(Column::Order)
+      .and_then(|col| grid.get(row, col))
+      .is_some_and(|slot| slot.origin.0 != row);
+    let order = match text(Column::Order) {
+      order if !order_spanned && !order.is_empty() && self.layout.is_order(order) => order.into(),
+      _ => PREVIOUS_ORDER_PLACEHOLDER.into(),
+    };
+
+    let (name, teacher) = match text(Column::Teacher) {
+      "" => self.layout.split_teacher(text(Column::Lecture)),
+      teacher => (empty_to_none!(Some(text(Column::Lecture))), Some(teacher.into())),
+    };
+
+    RawLecture {
+      order: Some(order),
+      group_name,
+      subgroup,
+      name,
+      teacher,
+      classroom: empty_to_none!(Some(text(Column::Classroom))),
+      cells: vec![],
+    }
+  }
+
   fn parse_raw_lectures<S: AsRef<str>, I: Iterator<Item = Vec<S>> + Clone>(&self, rows: Peekable<I>) -> Vec<RawLecture> {
-    let mut anchor = "Unknown".into();
+    let mut anchor = None;
     rows
-      .map(|row| self.parse_raw_lecture(row.iter().peekable(), &mut anchor))
+      .map(|row| {
+        let cells = row.iter().map(|cell| cell.as_ref().to_string()).collect();
+        RawLecture { cells, ..self.parse_raw_lecture(row.iter().peekable(), &mut anchor) }
+      })
       .collect()
   }
 
   fn parse_raw_lecture<S: AsRef<str>, I: Iterator<Item = S> + Clone>(
     &self,
     mut row: Peekable<I>,
-    anchor: &mut Box<str>,
+    anchor: &mut Option<Box<str>>,
   ) -> RawLecture {
     let ((group_name, subgroup), (order, lecture_name)) = match row.next() {
       Some(val) if self.is_group_name(val.as_ref()) => {
         let val = val.as_ref();
-        if &**anchor != val {
-          *anchor = Box::from(val);
+        if anchor.as_deref() != Some(val) {
+          *anchor = Some(Box::from(val));
         }
 
-        (parse_group_subgroup_pair(val), parse_order_lecture_pair(row.next(), &mut row))
+        (self.layout.split_group(val), self.parse_order_lecture_pair(row.next(), &mut row))
       }
-      Some(val) => (parse_group_subgroup_pair(&anchor), parse_order_lecture_pair(Some(val), &mut row)),
+      Some(val) => (
+        anchor
+          .as_deref()
+          .map(|anchor| self.layout.split_group(anchor))
+          .unwrap_or_default(),
+        self.parse_order_lecture_pair(Some(val), &mut row),
+      ),
       _ => return RawLecture::default(),
     };
 
-    let (lecture_name, teacher) = split_teacher(lecture_name);
+    let (lecture_name, teacher) = match lecture_name {
+      Some(lecture_name) => self.layout.split_teacher(&lecture_name),
+      None => (None, None),
+    };
 
     let classroom = match row.next() {
       Some(x) if !x.as_ref().trim().is_empty() => Some(Box::from(x.as_ref().trim())),
       _ => None,
     };
 
-    RawLecture { order: Some(order), group_name, subgroup, name: lecture_name, teacher, classroom }
+    RawLecture { order: Some(order), group_name, subgroup, name: lecture_name, teacher, classroom, cells: vec![] }
   }
 
-  fn assign_to_groups<I: Iterator<Item = RawLecture>>(self, lectures: I, is_week_even: bool) -> Vec<Group> {
+  fn assign_to_groups<I: Iterator<Item = RawLecture>>(
+    &self,
+    lectures: I,
+    date: DateTime,
+    overrides: &mut Vec<Override>,
+    report: &mut ParseReport,
+  ) -> Vec<Group> {
     let mut prev: Option<RawLecture> = None;
 
     let mut groups = self
@@ -108,61 +243,116 @@ impl SnapshotParser4 {
       .map(|name| Group::new(name, vec![]))
       .collect::<Vec<Group>>();
 
-    lectures
-      .map(|mut lecture| {
-        if matches!(lecture.order.as_deref(), Some(PREVIOUS_ORDER_PLACEHOLDER)) {
-          lecture.order = prev.as_ref().and_then(|p| p.order.clone())
+    for mut lecture in lectures {
+      if matches!(lecture.order.as_deref(), Some(PREVIOUS_ORDER_PLACEHOLDER)) {
+        lecture.order = prev.as_ref().and_then(|p| p.order.clone());
+        match lecture.order.as_deref() {
+          Some(order) => report.push(&lecture.cells, ReportReason::PreviousOrder { order: order.into() }),
+          None => report.push(&lecture.cells, ReportReason::BadOrder),
         }
-        prev = Some(lecture.clone());
-        lecture
-      })
-      .filter(|l| l.group_name.is_some() && !matches!(l.name.as_deref(), None | Some("Нет") | Some("нет")))
-      .for_each(|lecture| {
-        let group_name = lecture.group_name.as_deref().unwrap();
-        let group = groups.iter_mut().find(|x| x.name() == group_name);
-        if group.is_none() {
-          return;
+      }
+      prev = Some(lecture.clone());
+
+      let Some(group_name) = lecture.group_name.clone() else {
+        report.push(&lecture.cells, ReportReason::NoGroup);
+        continue;
+      };
+
+      if lecture.name.is_none() {
+        report.push(&lecture.cells, ReportReason::NoLectureName);
+        continue;
+      }
+
+      overrides.extend(split_order(lecture.order.as_deref()).map(|order| Override {
+        group_name: group_name.clone(),
+        order: order.into(),
+        subgroup: lecture.subgroup.clone(),
+      }));
+
+      if lecture
+        .name
+        .as_deref()
+        .is_some_and(|name| self.layout.is_no_lecture(name))
+      {
+        continue;
+      }
+
+      let idx = match groups.iter().position(|x| x.name() == &*group_name) {
+        Some(idx) => idx,
+        None => {
+          debug!(target: "parser", "found unknown group {}", group_name);
+          report.push(&lecture.cells, ReportReason::UnknownGroup { name: group_name.to_string() });
+          groups.push(Group::new(&group_name, vec![]));
+          groups.len() - 1
         }
-        let lectures = self.expand_raw_lecture(lecture, is_week_even);
-        group.unwrap().push_lectures(lectures.into_iter());
-      });
+      };
+      let lectures = self.expand_raw_lecture(lecture, date, report);
+      groups[idx].push_lectures(lectures.into_iter());
+    }
     groups
   }
 
-  fn expand_raw_lecture(&self, lecture: RawLecture, is_week_even: bool) -> Vec<Lecture> {
-    if matches!(lecture.name.as_deref(), None | Some("По расписанию") | Some("по расписанию")) {
-      if let Some(default_lecture) = self
+  /// Пара "по расписанию" берётся из основного расписания на этот день по номеру (и подгруппе, если она указана).
+  /// У пар старого формата номера нет: берётся первая подходящая, а номера - со страницы
+  fn expand_raw_lecture(&self, lecture: RawLecture, date: DateTime, report: &mut ParseReport) -> Vec<Lecture> {
+    if lecture
+      .name
+      .as_deref()
+      .is_some_and(|name| self.layout.is_by_schedule(name))
+    {
+      let orders = split_order(lecture.order.as_deref()).collect::<Vec<&str>>();
+      let day = self
         .default_lectures
-        .group(lecture.group_name.unwrap())
-        .and_then(|mut lectures| {
-          lectures.find(|lecture| match lecture.week {
-            LectureWeek::Even => is_week_even,
-            LectureWeek::Odd => !is_week_even,
-            LectureWeek::Every => true,
-          })
+        .day(lecture.group_name.as_deref().unwrap(), date.weekday(), is_week_even(date))
+        .into_iter()
+        .filter(|default| same_subgroup(default.subgroup(), lecture.subgroup.as_deref()))
+        .collect::<Vec<&DefaultLecture>>();
+      let mut default_lectures = day
+        .iter()
+        .filter(|default| {
+          default
+            .order()
+            .is_some_and(|order| orders.is_empty() || orders.contains(&order))
         })
-      {
-        return lecture
-          .order
-          .unwrap_or_else(|| default_lecture.order().unwrap_or_default().into())
-          .split(',')
-          .map(|order| {
-            Lecture::new(
-              Some(order.trim().into()),
-              default_lecture.name().into(),
-              lecture
-                .classroom
-                .clone()
-                .or_else(|| default_lecture.classroom().map(Into::into)),
-              lecture
-                .subgroup
-                .clone()
-                .or_else(|| default_lecture.subgroup().map(Into::into)),
-              lecture
-                .teacher
-                .clone()
-                .or_else(|| default_lecture.teacher().map(Into::into)),
-            )
+        .copied()
+        .collect::<Vec<&DefaultLecture>>();
+      if default_lectures.is_empty() {
+        default_lectures.extend(day.iter().find(|default| default.order().is_none()));
+      }
+
+      if default_lectures.is_empty() {
+        report.push(&lecture.cells, ReportReason::NoDefaultLecture);
+      }
+
+      if !default_lectures.is_empty() {
+        return default_lectures
+          .into_iter()
+          .flat_map(|default_lecture| {
+            report.push(&lecture.cells, ReportReason::DefaultLecture { name: default_lecture.name().into() });
+            let orders = match default_lecture.order() {
+              Some(order) => vec![Some(order)],
+              None if orders.is_empty() => vec![None],
+              None => orders.iter().map(|order| Some(*order)).collect(),
+            };
+            let lecture = &lecture;
+            orders.into_iter().map(move |order| {
+              Lecture::new(
+                order.map(Into::into),
+                default_lecture.name().into(),
+                lecture
+                  .classroom
+                  .clone()
+                  .or_else(|| default_lecture.classroom().map(Into::into)),
+                lecture
+                  .subgroup
+                  .clone()
+                  .or_else(|| default_lecture.subgroup().map(Into::into)),
+                lecture
+                  .teacher
+                  .clone()
+                  .or_else(|| default_lecture.teacher().map(Into::into)),
+              )
+            })
           })
           .collect();
       }
@@ -184,46 +374,106 @@ impl SnapshotParser4 {
       .collect()
   }
 
+  /// Дополняет группы, которые есть на странице, парами из основного расписания на этот день, которые страница
+  /// не заменила и не отменила. Группы без изменений не трогаются: их на странице нет и в снапшот они не попадают
+  fn merge_default_lectures(&self, groups: &mut Vec<Group>, date: DateTime, overrides: &[Override]) {
+    for default_group in self.default_lectures.groups() {
+      let on_page = overrides.iter().any(|o| *o.group_name == *default_group.name)
+        || groups
+          .iter()
+          .any(|group| group.name() == default_group.name && group.has_lectures());
+      if !on_page {
+        continue;
+      }
+
+      let lectures = self
+        .default_lectures
+        .day(&default_group.name, date.weekday(), is_week_even(date))
+        .into_iter()
+        .filter(|default| default.weekday.is_some())
+        .filter(|default| {
+          !overrides.iter().any(|o| {
+            *o.group_name == *default_group.name
+              && default.order() == Some(&o.order)
+              && same_subgroup(default.subgroup(), o.subgroup.as_deref())
+          })
+        })
+        .map(|default| default.to_lecture())
+        .collect::<Vec<Lecture>>();
+
+      if lectures.is_empty() {
+        continue;
+      }
+
+      match groups.iter_mut().find(|group| group.name() == default_group.name) {
+        Some(group) => group.push_lectures(lectures.into_iter()),
+        None => groups.push(Group::new(&default_group.name, lectures)),
+      }
+    }
+  }
+
+  /// `(order?, lecture_name?)`
+  fn parse_order_lecture_pair<S: AsRef<str>, I: Iterator<Item = S>>(
+    &self,
+    raw: Option<S>,
+    row: &mut I,
+  ) -> (Box<str>, Option<Box<str>>) {
+    match raw {
+      Some(val) if self.layout.is_order(val.as_ref()) => (Box::from(val.as_ref()), row.next().map(|x| x.as_ref().into())),
+      Some(val) => (Box::from(PREVIOUS_ORDER_PLACEHOLDER), Some(val.as_ref().into())),
+      None => (Box::from(PREVIOUS_ORDER_PLACEHOLDER), None),
+    }
+  }
+
   fn is_group_name(&self, name: &str) -> bool {
     let name = name.split(' ').next().unwrap_or_default();
-    self.group_names.iter().any(|group| group.as_ref() == name)
+    self.group_names.iter().any(|group| group.as_ref() == name) || looks_like_group_name(name)
   }
 }
 
-/// `(order?, lecture_name?)`
-fn parse_order_lecture_pair<S: AsRef<str>, I: Iterator<Item = S>>(raw: Option<S>, row: &mut I) -> (Box<str>, Option<Box<str>>) {
-  match raw {
-    Some(val) if is_correct_order(&val) => (Box::from(val.as_ref()), row.next().map(|x| x.as_ref().into())),
-    Some(val) => (Box::from(PREVIOUS_ORDER_PLACEHOLDER), Some(val.as_ref().into())),
-    None => (Box::from(PREVIOUS_ORDER_PLACEHOLDER), None),
-  }
+/// Без заголовка пары начинаются с первой строки с группой: выше - дата и, возможно, нераспознанный заголовок
+fn first_group_row<F: Fn(&str) -> bool>(grid: &Grid, columns: &ColumnMap, is_group_name: F) -> usize {
+  let Some(col) = columns.get(Column::Group) else { return grid.height() };
+  (0..grid.height())
+    .find(|row| is_group_name(grid.column_text(*row, col).trim()))
+    .unwrap_or(grid.height())
 }
 
-fn is_correct_order<S: AsRef<str>>(raw: S) -> bool {
-  const SKIP_CHARS: [char; 6] = ['(', ')', ',', '.', 'ч', ' '];
-  raw
-    .as_ref()
-    .chars()
-    .all(|c| SKIP_CHARS.contains(&c) || c.is_numeric())
+fn is_week_even(date: DateTime) -> bool {
+  date.iso_week().week().is_multiple_of(2)
 }
 
-/// `(lecture_name, teacher_name)`
-fn split_teacher<S: AsRef<str>>(raw: Option<S>) -> (Option<Box<str>>, Option<Box<str>>) {
-  let raw = match raw {
-    Some(x) => x,
-    None => return (None, None),
-  };
+/// Пара без подгруппы относится ко всем подгруппам
+fn same_subgroup(lhs: Option<&str>, rhs: Option<&str>) -> bool {
+  lhs.is_none() || rhs.is_none() || lhs == rhs
+}
 
-  if let Some((name, teacher)) = raw.as_ref().rsplit_once(',') {
-    return (Some(name.trim().into()), empty_to_none!(Some(teacher.trim())));
-  }
-  (empty_to_none!(Some(raw.as_ref())), None)
+fn split_order(order: Option<&str>) -> impl Iterator<Item = &str> {
+  order
+    .unwrap_or_default()
+    .split(',')
+    .map(|order| order.trim())
+    .filter(|order| !order.is_empty())
 }
 
-/// `(group_name?, subgroup?)`
-fn parse_group_subgroup_pair<S: AsRef<str>>(raw: S) -> (Option<Box<str>>, Option<Box<str>>) {
-  let mut split = raw.as_ref().split(' ').map(|x| x.trim());
-  (empty_to_none!(split.next()), empty_to_none!(split.next()))
+/// Название группы вида `Ир3-21` или `ЗК1-22`: 1-3 буквы кириллицей (первая - заглавная), номер и две цифры года
+pub fn looks_like_group_name(name: &str) -> bool {
+  let Some((prefix, year)) = name.split_once('-') else { return false };
+  let letters = prefix.trim_end_matches(|c: char| c.is_ascii_digit());
+  let number = &prefix[letters.len()..];
+
+  let is_cyrillic = |c: char| matches!(c, 'А'..='я' | 'Ё' | 'ё');
+  let first_is_upper = letters
+    .chars()
+    .next()
+    .is_some_and(|c| is_cyrillic(c) && c.is_uppercase());
+
+  first_is_upper
+    && letters.chars().count() <= 3
+    && letters.chars().all(is_cyrillic)
+    && (1..=2).contains(&number.len())
+    && year.len() == 2
+    && year.chars().all(|c| c.is_ascii_digit())
 }
 
 #[cfg(test)]
@@ -237,7 +487,7 @@ mod tests {
   #[case("1,2,3(1ч)")]
   #[case("")]
   fn correct_order(#[case] order: &str) {
-    assert!(is_correct_order(order))
+    assert!(Layout::default().is_order(order))
   }
 
   #[rstest]
@@ -245,12 +495,136 @@ mod tests {
   #[case("Информационные технологии, Иванов И.Л.")]
   #[case("МДК.01.01 Разработка программных модулей, Пикселькина О.И.")]
   fn incorrect_order(#[case] order: &str) {
-    assert!(!is_correct_order(order))
+    assert!(!Layout::default().is_order(order))
+  }
+
+  #[rstest]
+  #[case("Ир3-21")]
+  #[case("ЗК1-22")]
+  #[case("С1-22")]
+  #[case("Кс5-20")]
+  #[case("Ит1-24")]
+  fn group_name_pattern(#[case] name: &str) {
+    assert!(looks_like_group_name(name))
+  }
+
+  #[rstest]
+  #[case("")]
+  #[case("214")]
+  #[case("1-14")]
+  #[case("Физ-ра")]
+  #[case("ир3-21")]
+  #[case("Ир3-2021")]
+  #[case("Иванов И.Л.")]
+  #[case("МДК.01.01 Разработка программных модулей")]
+  fn not_group_name_pattern(#[case] name: &str) {
+    assert!(!looks_like_group_name(name))
   }
 
   #[rstest]
   #[case("Ир3-21 2 п/г", (Some("Ир3-21".into()), Some("2".into())))]
   fn correct_splitting_group_name(#[case] name: &str, #[case] expect: (Option<Box<str>>, Option<Box<str>>)) {
-    assert_eq!(parse_group_subgroup_pair(name), expect)
+    assert_eq!(Layout::default().split_group(name), expect)
+  }
+
+  #[rstest]
+  fn report_skipped_rows() {
+    let rows = [
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Группа", "Пара", "Дисциплина", "Аудитория"],
+      vec!["1", "Информационные технологии, Иванов И.Л.", "214"],
+      vec!["Ир3-21", "1", "Информационные технологии, Иванов И.Л.", "214"],
+      vec!["2", "Нет"],
+    ];
+    let table = Table::from_text_rows(rows);
+    let (snapshot, report) = SnapshotParser4::new(DateTime::now())
+      .with_groups(["Ир3-21"].iter())
+      .parse(table);
+
+    assert_eq!(snapshot.group("Ир3-21").unwrap().lectures().len(), 1);
+    let entries = report.entries().collect::<Vec<_>>();
+    assert!(matches!(entries[..], [entry] if entry.reason() == &ReportReason::NoGroup && entry.is_skipped()));
+  }
+
+  #[rstest]
+  fn merge_default_lectures() {
+    // 18 октября 2023 - среда 42-й, чётной недели
+    let timetable = DefaultLectures::from_toml(
+      r#"
+      [[groups]]
+      name = "Ир3-21"
+      lectures = [
+        { weekday = "wed", order = "1", name = "Информационные технологии", classroom = "214" },
+        { weekday = "wed", order = "2", name = "История", classroom = "102" },
+        { weekday = "wed", order = "3", week = "odd", name = "Физкультура" },
+        { weekday = "wed", order = "3", week = "even", name = "Экономика" },
+        { weekday = "wed", order = "4", name = "Литература" },
+      ]
+
+      [[groups]]
+      name = "С1-22"
+      lectures = [{ weekday = "wed", order = "1", name = "Сети" }]
+      "#,
+    )
+    .unwrap();
+
+    let rows = [
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Группа", "Пара", "Дисциплина", "Аудитория"],
+      vec!["Ир3-21", "1", "Математика, Петров А.А.", "301"],
+      vec!["2", "Нет"],
+      vec!["4", "По расписанию", "305"],
+    ];
+    let table = Table::from_text_rows(rows);
+    let reference = FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, 16, 9, 0, 0)
+      .unwrap();
+    let (snapshot, report) = SnapshotParser4::new(reference)
+      .with_groups(["Ир3-21", "С1-22"].iter())
+      .with_default_lectures(timetable)
+      .parse(table);
+
+    let lectures = snapshot
+      .group("Ир3-21")
+      .unwrap()
+      .lectures()
+      .map(|lecture| (lecture.order().unwrap(), lecture.name(), lecture.classroom()))
+      .collect::<Vec<_>>();
+    assert_eq!(lectures, vec![("1", "Математика", Some("301")), ("3", "Экономика", None), ("4", "Литература", Some("305"))]);
+    // группы без изменений на странице не дополняются
+    assert!(snapshot.group("С1-22").is_none());
+    assert!(
+      matches!(report.entries().next().map(|e| e.reason()), Some(ReportReason::DefaultLecture { name }) if name == "Литература")
+    );
+  }
+
+  #[rstest]
+  fn legacy_default_lectures() {
+    let timetable = DefaultLectures::from_json(
+      r#"[{ "name": "Ир3-21", "lectures": [{ "week": "Every", "name": "Физкультура", "classroom": "спортзал" }] }]"#,
+    )
+    .unwrap();
+    let rows = [
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Группа", "Пара", "Дисциплина", "Аудитория"],
+      vec!["Ир3-21", "1", "Математика, Петров А.А.", "301"],
+      vec!["2,3", "По расписанию"],
+    ];
+    let (snapshot, _) = SnapshotParser4::new(DateTime::now())
+      .with_groups(["Ир3-21"].iter())
+      .with_default_lectures(timetable)
+      .parse(Table::from_text_rows(rows));
+
+    let lectures = snapshot
+      .group("Ир3-21")
+      .unwrap()
+      .lectures()
+      .map(|lecture| (lecture.order().unwrap(), lecture.name(), lecture.classroom()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      lectures,
+      vec![("1", "Математика", Some("301")), ("2", "Физкультура", Some("спортзал")), ("3", "Физкультура", Some("спортзал"))]
+    );
   }
 }
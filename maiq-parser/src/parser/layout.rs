@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::DateError;
+use crate::Error;
+use maiq_time::*;
+
+use super::columns::*;
+use super::impls::looks_like_group_name;
+use super::parse_date::parse_date;
+use super::table::Grid;
+use super::table::Table;
+
+const DEFAULT_ORDER: &str = r"^[\d (),.ч]*$";
+const DEFAULT_GROUP: &str = r"^(?P<group>\S+)(?:\s+(?P<subgroup>\S+))?";
+const DEFAULT_TEACHER: &str = r"^(?P<name>.*),(?P<teacher>[^,]*)$";
+
+/// Описание раскладки таблицы изменений для [`super::impls::DeclarativeParser`]. Загружается при старте, так что
+/// мелкие изменения на странице правятся конфигом, а не новым агентом.
+///
+/// В TOML выглядит так, все поля необязательные (значения по умолчанию - как у [`super::impls::SnapshotParser4`]):
+/// ```toml
+/// # названия столбцов в дополнение к стандартным, сравниваются по началу без учёта регистра
+/// [headers]
+/// teacher = ["ФИО"]
+/// classroom = ["Место"]
+///
+/// # ячейка с номером пары целиком
+/// order = '^[\d (),.ч]*$'
+/// # группа и подгруппа из ячейки группы
+/// group = '^(?P<group>\S+)(?:\s+(?P<subgroup>\S+))?'
+/// # дисциплина и преподаватель из ячейки дисциплины, если отдельного столбца с преподавателем нет
+/// teacher = '^(?P<name>.*),(?P<teacher>[^,]*)$'
+///
+/// # пара отменена
+/// no_lecture = ["Нет"]
+/// # пара берётся из основного расписания
+/// by_schedule = ["По расписанию"]
+///
+/// # столбцы по порядку, если строки заголовка на странице не нашлось
+/// columns = ["group", "order", "lecture", "teacher", "classroom"]
+/// ```
+/// Маркеры сравниваются со всей ячейкой без учёта регистра. В `columns` обязательны `group`, `order` и `lecture`
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "RawLayout")]
+pub struct Layout {
+  headers: HashMap<Column, Vec<String>>,
+  order: Regex,
+  group: Regex,
+  teacher: Regex,
+  no_lecture: Vec<String>,
+  by_schedule: Vec<String>,
+  columns: Option<ColumnMap>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLayout {
+  #[serde(default)]
+  headers: HashMap<Column, Vec<String>>,
+  #[serde(default = "default_order")]
+  order: String,
+  #[serde(default = "default_group")]
+  group: String,
+  #[serde(default = "default_teacher")]
+  teacher: String,
+  #[serde(default = "default_no_lecture")]
+  no_lecture: Vec<String>,
+  #[serde(default = "default_by_schedule")]
+  by_schedule: Vec<String>,
+  #[serde(default)]
+  columns: Option<Vec<Column>>,
+}
+
+fn default_order() -> String {
+  DEFAULT_ORDER.into()
+}
+
+fn default_group() -> String {
+  DEFAULT_GROUP.into()
+}
+
+fn default_teacher() -> String {
+  DEFAULT_TEACHER.into()
+}
+
+fn default_no_lecture() -> Vec<String> {
+  vec!["Нет".into()]
+}
+
+fn default_by_schedule() -> Vec<String> {
+  vec!["По расписанию".into()]
+}
+
+impl TryFrom<RawLayout> for Layout {
+  type Error = String;
+
+  fn try_from(raw: RawLayout) -> Result<Self, Self::Error> {
+    let columns = match raw.columns {
+      Some(columns) => {
+        Some(ColumnMap::positional(&columns).ok_or("columns must name group, order and lecture, each at most once")?)
+      }
+      None => None,
+    };
+    let regex = |raw: &str| Regex::new(raw).map_err(|err| err.to_string());
+    let lowercase = |markers: Vec<String>| {
+      markers
+        .into_iter()
+        .map(|marker| marker.trim().to_lowercase())
+        .collect()
+    };
+    Ok(Self {
+      headers: raw.headers,
+      order: regex(&raw.order)?,
+      group: regex(&raw.group)?,
+      teacher: regex(&raw.teacher)?,
+      no_lecture: lowercase(raw.no_lecture),
+      by_schedule: lowercase(raw.by_schedule),
+      columns,
+    })
+  }
+}
+
+impl Default for Layout {
+  fn default() -> Self {
+    let raw = RawLayout {
+      headers: HashMap::new(),
+      order: default_order(),
+      group: default_group(),
+      teacher: default_teacher(),
+      no_lecture: default_no_lecture(),
+      by_schedule: default_by_schedule(),
+      columns: None,
+    };
+    Self::try_from(raw).unwrap()
+  }
+}
+
+impl Layout {
+  pub fn from_toml(raw: &str) -> Result<Self, Error> {
+    toml::from_str(raw).map_err(Error::LayoutToml)
+  }
+
+  pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    let path = path.as_ref();
+    Self::from_toml(&std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?)
+  }
+
+  /// Строка заголовка с учётом дополнительных названий из `[headers]`, см. [`ColumnMap::find_header_with`]
+  pub fn find_header(&self, grid: &Grid) -> Option<(usize, ColumnMap)> {
+    ColumnMap::find_header_with(grid, &self.headers)
+  }
+
+  /// Столбцы по порядку из `columns`, если строки заголовка нет
+  pub fn columns(&self) -> Option<&ColumnMap> {
+    self.columns.as_ref()
+  }
+
+  /// Насколько таблица похожа на страницу изменений с этой раскладкой, от `0.0` до `1.0`: дата в первой строке,
+  /// строка заголовка и доля строк с группой
+  pub fn detect(&self, table: &Table, now: DateTime) -> f32 {
+    let rows = table.text_rows();
+    let Some(first) = rows.first() else { return 0.0 };
+
+    // год может не сойтись с сегодняшним днём, но дата в заголовке всё равно есть
+    let has_date = !matches!(parse_date(&mut std::iter::once(first.clone()), now), Err(DateError::NotFound(_)));
+    let has_header = self.find_header(&table.grid()).is_some();
+
+    let rows = rows
+      .iter()
+      .skip(1)
+      .filter(|row| row.iter().any(|cell| !cell.trim().is_empty()))
+      .collect::<Vec<_>>();
+    let with_group = rows
+      .iter()
+      .filter(|row| {
+        row.iter().any(|cell| {
+          self
+            .split_group(cell)
+            .0
+            .is_some_and(|group| looks_like_group_name(&group))
+        })
+      })
+      .count();
+    let groups = match rows.len() {
+      0 => 0.0,
+      len => with_group as f32 / len as f32,
+    };
+
+    0.3 * has_date as u8 as f32 + 0.4 * has_header as u8 as f32 + 0.3 * groups
+  }
+
+  pub fn is_order(&self, raw: &str) -> bool {
+    self.order.is_match(raw)
+  }
+
+  /// `(group?, subgroup?)`
+  pub fn split_group(&self, raw: &str) -> (Option<Box<str>>, Option<Box<str>>) {
+    match self.group.captures(raw.trim()) {
+      Some(captures) => (capture(&captures, "group"), capture(&captures, "subgroup")),
+      None => (None, None),
+    }
+  }
+
+  /// `(lecture_name?, teacher?)`. Если выражение не совпало, вся ячейка - название
+  pub fn split_teacher(&self, raw: &str) -> (Option<Box<str>>, Option<Box<str>>) {
+    match self.teacher.captures(raw) {
+      Some(captures) => {
+        let name = captures
+          .name("name")
+          .map(|name| name.as_str().trim())
+          .unwrap_or_default();
+        (Some(name.into()), capture(&captures, "teacher"))
+      }
+      None => (Some(raw.trim()).filter(|raw| !raw.is_empty()).map(Into::into), None),
+    }
+  }
+
+  pub fn is_no_lecture(&self, name: &str) -> bool {
+    is_marker(&self.no_lecture, name)
+  }
+
+  pub fn is_by_schedule(&self, name: &str) -> bool {
+    is_marker(&self.by_schedule, name)
+  }
+}
+
+fn capture(captures: &regex::Captures, name: &str) -> Option<Box<str>> {
+  captures
+    .name(name)
+    .map(|capture| capture.as_str().trim())
+    .filter(|capture| !capture.is_empty())
+    .map(Into::into)
+}
+
+fn is_marker(markers: &[String], name: &str) -> bool {
+  let name = name.trim().to_lowercase();
+  markers.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  fn defaults() {
+    let layout = Layout::from_toml("").unwrap();
+    assert!(layout.is_order("1,2,3(1ч)"));
+    assert!(!layout.is_order("Информационные технологии, Иванов И.Л."));
+    assert_eq!(layout.split_group("Ир3-21 2 п/г"), (Some("Ир3-21".into()), Some("2".into())));
+    assert_eq!(layout.split_group("Ир3-21"), (Some("Ир3-21".into()), None));
+    assert_eq!(layout.split_teacher("Математика , Петрова А.А."), (Some("Математика".into()), Some("Петрова А.А.".into())));
+    assert_eq!(layout.split_teacher("Физическая культура"), (Some("Физическая культура".into()), None));
+    assert!(layout.is_no_lecture("нет"));
+    assert!(layout.is_by_schedule("ПО РАСПИСАНИЮ"));
+  }
+
+  #[rstest]
+  fn custom() {
+    let layout = Layout::from_toml(
+      r#"
+      order = '^\d+( пара)?$'
+      group = '^(?P<group>\S+)(?:\s*\((?P<subgroup>\d)\))?$'
+      teacher = '^(?P<name>.+?)\s+\[(?P<teacher>.+)\]$'
+      no_lecture = ["Отмена", "—"]
+      by_schedule = ["По расп."]
+      "#,
+    )
+    .unwrap();
+    assert!(layout.is_order("2 пара"));
+    assert!(!layout.is_order("2,3"));
+    assert_eq!(layout.split_group("Ир3-21 (2)"), (Some("Ир3-21".into()), Some("2".into())));
+    assert_eq!(layout.split_teacher("История [Волков К.К.]"), (Some("История".into()), Some("Волков К.К.".into())));
+    assert_eq!(layout.split_teacher("История, Волков К.К."), (Some("История, Волков К.К.".into()), None));
+    assert!(layout.is_no_lecture("отмена"));
+    assert!(!layout.is_no_lecture("Нет"));
+    assert!(layout.is_by_schedule("по расп."));
+  }
+
+  #[rstest]
+  #[case(vec![vec!["Изменения на 18 октября"], vec!["Группа", "Пара", "Дисциплина"], vec!["Ир3-21", "1", "История"]], 0.85)]
+  #[case(vec![vec!["Изменения на 18 октября"], vec!["Ир3-21", "1", "История"], vec!["", "2", "Математика"]], 0.45)]
+  #[case(vec![vec!["Заместитель директора по УР"], vec!["Иванова И.И."]], 0.0)]
+  #[case(vec![], 0.0)]
+  fn detect(#[case] rows: Vec<Vec<&str>>, #[case] expected: f32) {
+    let now = FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, 16, 9, 0, 0)
+      .unwrap();
+    let score = Layout::default().detect(&Table::from_text_rows(rows), now);
+    assert!((score - expected).abs() < 1e-3, "{score} != {expected}");
+  }
+
+  #[rstest]
+  #[case("order = '('")]
+  #[case("unknown = 1")]
+  #[case("[headers]\nnot_a_column = [\"A\"]")]
+  #[case("columns = [\"group\", \"lecture\"]")]
+  #[case("columns = [\"group\", \"order\", \"lecture\", \"order\"]")]
+  fn invalid(#[case] raw: &str) {
+    assert!(matches!(Layout::from_toml(raw), Err(Error::LayoutToml(_))));
+  }
+}
@@ -1,11 +1,22 @@
+pub mod columns;
+pub mod control;
 pub mod default_lectures;
+pub mod event;
+pub mod fetch;
 pub mod impls;
+pub mod layout;
+pub mod registry;
 pub mod repeating;
+pub mod report;
+pub mod schedule;
+pub mod selection;
+pub mod source;
 pub mod table;
 
 mod parse_date;
 
-pub const GROUP_NAMES: [&str; 34] = [
+/// Используется, если список групп не передан в [`SnapshotParserBuilder`]
+pub const DEFAULT_GROUP_NAMES: [&str; 34] = [
   "Ит1-23", "Ит3-23", "Ит1-22", "Са1-22", "Са3-22", "С1-22", "С3-22", "Ир1-22", "Ир3-22", "Ир5-22", "Са1-21", "Са3-21", "С1-21",
   "С3-21", "Ип1-21", "Ип3-21", "Ип5-21", "Ир1-21", "Ир3-21", "Ир5-21", "С1-20", "С3-20", "Кс1-20", "Кс3-20", "Кс5-20", "Ип1-20",
   "Ип3-20", "Ир1-20", "Ир3-20", "Ир5-20", "С1-19", "С3-19", "ЗК1-21", "ЗК1-22",
@@ -13,29 +24,61 @@ pub const GROUP_NAMES: [&str; 34] = [
 
 pub const DEFAULT_TIME_BOUNDS: std::ops::Range<u32> = 7..18;
 
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Arc;
+
+use encoding_rs::Encoding;
 
 use url::Url;
 
 use self::default_lectures::DefaultLectures;
+use self::fetch::Fetcher;
+use self::fetch::HttpFetcher;
+use self::layout::Layout;
+use self::registry::AgentRegistry;
+use self::repeating::Days;
 use self::repeating::SnapshotParser;
+use self::repeating::SourceState;
+use self::report::ParseReport;
+use self::selection::TableSelection;
+use self::source::*;
 use self::table::Table;
 use crate::snapshot::*;
-use crate::utils::time::DateTime;
 use crate::Error;
+use maiq_time::*;
 
 pub trait SnapshotParserAgent {
+  /// Имя в [`AgentRegistry`] и в [`Snapshot::agent`]
+  const NAME: &'static str = "custom";
+
+  /// Насколько агенту подходит таблица, от `0.0` до `1.0`. [`AgentRegistry`] сначала пробует самого уверенного.
+  /// `now` нужен для дат без года. По умолчанию агент ни в чём не уверен и ни от чего не отказывается
+  fn detect(_: &Table, _: DateTime) -> f32 {
+    0.5
+  }
+
   fn new(fallback_date: DateTime) -> Self;
   fn with_groups<S: AsRef<str>, I: Iterator<Item = S>>(self, group_names: I) -> Self;
   fn with_default_lectures(self, lectures: DefaultLectures) -> Self;
-  fn parse(self, table: Table) -> Snapshot;
+  fn parse(self, table: Table) -> (Snapshot, ParseReport);
 }
 
 #[derive(Default)]
 pub struct SnapshotParserBuilder {
-  today_remote_url: Option<Url>,
-  next_remote_url: Option<Url>,
+  sources: Vec<(Source, Url, Option<AgentRegistry>)>,
   default_lectures: Option<DefaultLectures>,
+  latest: Vec<Snapshot>,
+  fetcher: Option<Arc<dyn Fetcher>>,
+  encoding: Option<&'static Encoding>,
+  group_names: Option<Vec<Box<str>>>,
+  group_discovery: bool,
+  strict_dates: bool,
+  clock: Option<SharedClock>,
+  layout: Option<Layout>,
+  registry: Option<AgentRegistry>,
+  table_selection: Option<TableSelection>,
 }
 
 impl SnapshotParserBuilder {
@@ -43,31 +86,222 @@ impl SnapshotParserBuilder {
     Self::default()
   }
 
+  /// Страница на сегодня для корпуса по умолчанию, см. [`DEFAULT_CAMPUS`]
   pub fn with_today_url<U: AsRef<str>>(self, url: U) -> Result<Self, url::ParseError> {
-    Ok(Self { today_remote_url: Some(url.as_ref().parse()?), ..self })
+    self.with_source(Source::today(DEFAULT_CAMPUS), url)
   }
 
+  /// Страница на завтра для корпуса по умолчанию
   pub fn with_next_url<U: AsRef<str>>(self, url: U) -> Result<Self, url::ParseError> {
-    Ok(Self { next_remote_url: Some(url.as_ref().parse()?), ..self })
+    self.with_source(Source::next(DEFAULT_CAMPUS), url)
+  }
+
+  /// Страница, которую разбирают агенты по умолчанию, см. [`SnapshotParserBuilder::with_registry`]. Повторная
+  /// регистрация того же источника заменяет предыдущую
+  pub fn with_source<U: AsRef<str>>(self, source: Source, url: U) -> Result<Self, url::ParseError> {
+    self.push_source(source, url, None)
+  }
+
+  /// Страница со своим агентом, без подбора и запасных агентов
+  pub fn with_source_agent<P: SnapshotParserAgent + 'static, U: AsRef<str>>(
+    self,
+    source: Source,
+    url: U,
+  ) -> Result<Self, url::ParseError> {
+    self.push_source(source, url, Some(AgentRegistry::new().with_agent::<P>()))
+  }
+
+  /// Страница, которую разбирает [`DeclarativeParser`] с этой раскладкой
+  pub fn with_source_layout<U: AsRef<str>>(self, source: Source, url: U, layout: Layout) -> Result<Self, url::ParseError> {
+    self.push_source(source, url, Some(AgentRegistry::new().with_layout(layout)))
+  }
+
+  /// Страница со своим набором агентов
+  pub fn with_source_registry<U: AsRef<str>>(
+    self,
+    source: Source,
+    url: U,
+    registry: AgentRegistry,
+  ) -> Result<Self, url::ParseError> {
+    self.push_source(source, url, Some(registry))
+  }
+
+  /// Агенты для источников без своего. По умолчанию - только агент из [`SnapshotParserBuilder::build`]
+  pub fn with_registry(self, registry: AgentRegistry) -> Self {
+    Self { registry: Some(registry), ..self }
+  }
+
+  /// Добавляет к агентам по умолчанию [`impls::DeclarativeParser`] с этой раскладкой. При равной уверенности он
+  /// пробуется первым
+  pub fn with_layout(self, layout: Layout) -> Self {
+    Self { layout: Some(layout), ..self }
+  }
+
+  /// См. [`Layout`]
+  pub fn with_layout_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+    Ok(self.with_layout(Layout::read(path)?))
+  }
+
+  fn push_source<U: AsRef<str>>(
+    mut self,
+    source: Source,
+    url: U,
+    agents: Option<AgentRegistry>,
+  ) -> Result<Self, url::ParseError> {
+    let url = url.as_ref().parse()?;
+    self.sources.retain(|(s, ..)| *s != source);
+    self.sources.push((source, url, agents));
+    Ok(self)
   }
 
   pub fn with_default_lectures(self, lectures: DefaultLectures) -> Self {
     Self { default_lectures: Some(lectures), ..self }
   }
 
-  pub fn build<P: SnapshotParserAgent + Send + Sync + 'static>(self) -> Result<SnapshotParser<P>, Error> {
+  /// Основное расписание из TOML или JSON, см. [`DefaultLectures`]
+  pub fn with_default_lectures_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+    Ok(self.with_default_lectures(DefaultLectures::read(path)?))
+  }
+
+  pub fn with_groups<S: AsRef<str>, I: IntoIterator<Item = S>>(self, group_names: I) -> Self {
+    Self { group_names: Some(group_names.into_iter().map(|name| name.as_ref().into()).collect()), ..self }
+  }
+
+  /// См. [`read_group_names`]
+  pub fn with_groups_file<P: AsRef<Path>>(self, path: P) -> Result<Self, Error> {
+    Ok(self.with_groups(read_group_names(path)?))
+  }
+
+  /// Добавлять в список группы, найденные в таблице, но отсутствующие в нём. Без этого о них только предупреждается
+  pub fn with_group_discovery(self, enabled: bool) -> Self {
+    Self { group_discovery: enabled, ..self }
+  }
+
+  /// Страница без даты - ошибка [`Error::Date`], а не снапшот на запасную дату с
+  /// [`report::ReportReason::FallbackDate`] в отчёте. Запасная дата годится для отладки, но не для рассылки: страница
+  /// "на завтра" получила бы сегодняшнюю дату
+  pub fn with_strict_dates(self, enabled: bool) -> Self {
+    Self { strict_dates: enabled, ..self }
+  }
+
+  /// По умолчанию страницы загружаются по http через [`HttpFetcher`]
+  pub fn with_fetcher<F: Fetcher + 'static>(self, fetcher: F) -> Self {
+    Self { fetcher: Some(Arc::new(fetcher)), ..self }
+  }
+
+  /// Какую таблицу страницы разбирать, по умолчанию [`TableSelection::Last`]
+  pub fn with_table_selection(self, selection: TableSelection) -> Self {
+    Self { table_selection: Some(selection), ..self }
+  }
+
+  /// Кодировка страниц, по умолчанию `windows-1251`
+  pub fn with_encoding(self, encoding: &'static Encoding) -> Self {
+    Self { encoding: Some(encoding), ..self }
+  }
+
+  /// По умолчанию [`SystemClock`]. От часов зависят дата таблицы без года и окна опроса
+  /// [`repeating::RepeatingSnapshotParser`]
+  pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+    Self { clock: Some(Arc::new(clock)), ..self }
+  }
+
+  /// Последний снапшот источника, о котором уже уведомили, например, восстановленный из базы после рестарта.
+  /// Первый же тик сравнит свежую страницу с восстановленным снапшотом на ту же дату, с какой бы страницы он ни был
+  pub fn with_latest(mut self, source: Source, snapshot: Snapshot) -> Self {
+    self.latest.retain(|latest| latest.source() != Some(&source));
+    self.latest.push(snapshot.with_source(source));
+    self
+  }
+
+  /// Последний известный снапшот на сегодня для корпуса по умолчанию
+  pub fn with_latest_today(self, snapshot: Snapshot) -> Self {
+    self.with_latest(Source::today(DEFAULT_CAMPUS), snapshot)
+  }
+
+  /// Последний известный снапшот на завтра для корпуса по умолчанию
+  pub fn with_latest_next(self, snapshot: Snapshot) -> Self {
+    self.with_latest(Source::next(DEFAULT_CAMPUS), snapshot)
+  }
+
+  pub fn build<P: SnapshotParserAgent + Send + Sync + 'static>(mut self) -> Result<SnapshotParser<P>, Error> {
+    let registry = self
+      .registry
+      .unwrap_or_else(|| AgentRegistry::new().with_agent::<P>());
+    let registry = match self.layout {
+      Some(layout) => AgentRegistry::new().with_layout(layout).chain(registry),
+      None => registry,
+    };
+
+    let sources: Vec<SourceState> = self
+      .sources
+      .into_iter()
+      .map(|(source, url, agents)| {
+        let latest = self
+          .latest
+          .iter()
+          .position(|latest| latest.source() == Some(&source))
+          .map(|idx| self.latest.remove(idx));
+        let agents = agents.unwrap_or_else(|| registry.clone());
+        SourceState { source, url, agents, latest, report: None }
+      })
+      .collect();
+
+    let mut days = BTreeMap::new();
+    for state in sources.iter() {
+      if let Some(latest) = state.latest.as_ref() {
+        days.remember(latest.clone());
+      }
+    }
+
     let parser = SnapshotParser {
       default_lectures: self.default_lectures.unwrap_or_else(|| {
         warn!(target: "parser", "default lectures not set");
         DefaultLectures::default()
       }),
-      today_remote_url: self.today_remote_url,
-      next_remote_url: self.next_remote_url,
-      prev_today_snapshot: None,
-      prev_next_snapshot: None,
+      fetcher: self.fetcher.unwrap_or_else(|| Arc::new(HttpFetcher::default())),
+      encoding: self.encoding.unwrap_or(encoding_rs::WINDOWS_1251),
+      table_selection: self.table_selection.unwrap_or_default(),
+      group_names: self.group_names.unwrap_or_else(|| {
+        warn!(target: "parser", "group names not set; using defaults");
+        DEFAULT_GROUP_NAMES.iter().map(|&name| name.into()).collect()
+      }),
+      group_discovery: self.group_discovery,
+      strict_dates: self.strict_dates,
+      discovered_groups: vec![],
+      clock: self.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+      sources,
+      pages: Default::default(),
+      days,
       _marker: PhantomData,
     };
 
     Ok(parser)
   }
 }
+
+/// Читает список групп из файла: по группе на строку, пустые строки и строки, начинающиеся с `#`, пропускаются
+pub fn read_group_names<P: AsRef<Path>>(path: P) -> Result<Vec<Box<str>>, Error> {
+  let path = path.as_ref();
+  let raw = std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?;
+  Ok(parse_group_names(&raw))
+}
+
+fn parse_group_names(raw: &str) -> Vec<Box<str>> {
+  raw
+    .lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(Into::into)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  fn group_names_file() {
+    let raw = "# 4 корпус\nИр3-21\n\n  Ир1-21  \n";
+    assert_eq!(parse_group_names(raw), vec![Box::from("Ир3-21"), Box::from("Ир1-21")]);
+  }
+}
@@ -1,53 +1,229 @@
-use crate::utils::time::*;
+use crate::error::DateError;
+use maiq_time::*;
 
 const MONTHS: [&str; 12] =
   ["января", "февраля", "марта", "апреля", "мая", "июня", "июля", "августа", "сентября", "октября", "ноября", "декабря"];
 
-pub fn parse_date<S: AsRef<str>, T: Iterator<Item = Vec<S>>>(row: &mut T) -> Option<DateTime> {
-  let x = row.next().unwrap();
-  let mut split = x.first().unwrap().as_ref().split(' ');
+/// Основы названий дней недели и их падежные окончания: `среда`, `среду`, `в пятницу`. Слово должно целиком совпасть
+/// с основой и окончанием, иначе `среди` и `средство` тоже оказались бы средой
+const WEEKDAYS: [(&str, &[&str], Weekday); 7] = [
+  ("понедельник", MASCULINE, Weekday::Mon),
+  ("вторник", MASCULINE, Weekday::Tue),
+  ("сред", FEMININE, Weekday::Wed),
+  ("четверг", MASCULINE, Weekday::Thu),
+  ("пятниц", FEMININE, Weekday::Fri),
+  ("суббот", FEMININE, Weekday::Sat),
+  ("воскресень", NEUTER, Weekday::Sun),
+];
 
-  while let Some(word) = split.next() {
-    let day = match word.trim().parse::<u32>() {
-      Ok(day) => day,
-      _ => continue,
-    };
+const MASCULINE: &[&str] = &["", "а", "у", "ом", "е", "и", "ов", "ам", "ами", "ах"];
+const FEMININE: &[&str] = &["", "а", "ы", "е", "у", "ой", "ою", "ей", "ею", "ам", "ами", "ах"];
+const NEUTER: &[&str] = &["е", "я", "ю", "ем", "ям", "ями", "ях"];
+
+/// Дата из первой строки таблицы: `18 октября`, `05.06.2024`, `05.06.24` или `05.06`.
+///
+/// Если год не указан, берётся ближайший к `reference`, а если в заголовке есть день недели - ближайший, в котором
+/// дата на него выпадает. Так страница на 1 января, выложенная 31 декабря, получает следующий год, а оставшаяся
+/// с прошлого семестра - прошлый
+pub fn parse_date<S: AsRef<str>, T: Iterator<Item = Vec<S>>>(rows: &mut T, reference: DateTime) -> Result<DateTime, DateError> {
+  let row = rows.next().ok_or(DateError::NotFound(String::new()))?;
+  let header = row
+    .iter()
+    .map(|cell| cell.as_ref().trim())
+    .filter(|cell| !cell.is_empty())
+    .collect::<Vec<&str>>()
+    .join(" ");
+  if header.is_empty() {
+    return Err(DateError::NotFound(header));
+  }
+
+  let words = header
+    .split(|c: char| c.is_whitespace() || matches!(c, ',' | '(' | ')' | ':' | ';'))
+    .map(|word| word.trim_end_matches('.').to_lowercase())
+    .filter(|word| !word.is_empty())
+    .collect::<Vec<String>>();
+
+  let weekday = words.iter().find_map(|word| parse_weekday(word));
+  let (day, month, year) = find_date(&words).ok_or_else(|| DateError::NotFound(header.clone()))?;
+
+  let date = match year {
+    Some(year) => {
+      let date = NaiveDate::from_ymd_opt(year, month, day).ok_or(DateError::InvalidDate { day, month, year })?;
+      match weekday {
+        Some(weekday) if date.weekday() != weekday => return Err(DateError::WeekdayMismatch { date, weekday }),
+        _ => date,
+      }
+    }
+    None => infer_year(day, month, weekday, reference.date_naive())?,
+  };
+
+  Ok(timezone().midnight(date))
+}
 
-    if let Some(month) = split
-      .next()
-      .and_then(|month| MONTHS.iter().position(|&m| m == month).map(|x| x as u32 + 1))
-    {
-      return DateTime::now_date()
-        .with_day(day)
-        .and_then(|date| date.with_month(month).unwrap().with_nanosecond(0));
+/// `(день, месяц, год)`, если год указан
+fn find_date(words: &[String]) -> Option<(u32, u32, Option<i32>)> {
+  for (idx, word) in words.iter().enumerate() {
+    if let Some(date) = parse_numeric_date(word) {
+      return Some(date);
     }
+
+    let Ok(day) = word.parse::<u32>() else {
+      continue;
+    };
+
+    let Some(month) = words
+      .get(idx + 1)
+      .and_then(|month| MONTHS.iter().position(|m| m == month))
+    else {
+      continue;
+    };
+
+    let year = words
+      .get(idx + 2)
+      .and_then(|year| year.trim_end_matches('г').parse::<i32>().ok())
+      .filter(|year| *year >= 1000);
+    return Some((day, month as u32 + 1, year));
   }
 
   None
 }
 
+fn parse_numeric_date(word: &str) -> Option<(u32, u32, Option<i32>)> {
+  let parts = word.split('.').collect::<Vec<&str>>();
+  if !(2..=3).contains(&parts.len())
+    || parts
+      .iter()
+      .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+  {
+    return None;
+  }
+
+  let day = parts[0].parse().ok()?;
+  let month = parts[1].parse().ok()?;
+  let year = match parts.get(2) {
+    Some(year) if year.len() == 2 => Some(2000 + year.parse::<i32>().ok()?),
+    Some(year) if year.len() == 4 => Some(year.parse().ok()?),
+    Some(_) => return None,
+    None => None,
+  };
+  Some((day, month, year))
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+  WEEKDAYS
+    .iter()
+    .find(|(stem, endings, _)| {
+      word
+        .strip_prefix(stem)
+        .is_some_and(|ending| endings.contains(&ending))
+    })
+    .map(|(_, _, weekday)| *weekday)
+}
+
+fn infer_year(day: u32, month: u32, weekday: Option<Weekday>, reference: NaiveDate) -> Result<NaiveDate, DateError> {
+  let candidates = (reference.year() - 1..=reference.year() + 1)
+    .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+    .collect::<Vec<NaiveDate>>();
+
+  let closest = |dates: &mut dyn Iterator<Item = &NaiveDate>| {
+    dates
+      .min_by_key(|date| (**date - reference).num_days().abs())
+      .copied()
+  };
+
+  let Some(date) = closest(&mut candidates.iter()) else {
+    return Err(DateError::InvalidDate { day, month, year: reference.year() });
+  };
+
+  match weekday {
+    Some(weekday) => {
+      closest(&mut candidates.iter().filter(|date| date.weekday() == weekday)).ok_or(DateError::WeekdayMismatch { date, weekday })
+    }
+    None => Ok(date),
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use crate::parser::parse_date::*;
 
-  #[rstest]
-  #[case(vec![vec!["5 июня".into()]], (5, 6))]
-  #[case(vec![vec!["Стваыф 5 июля авыфавыф".into()]], (5, 7))]
-  #[case(vec![vec!["АВыфавыф 24 февраля fdjska sadf".into()]], (24, 2))]
-  fn simple(#[case] raw: Vec<Vec<String>>, #[case] expect: (u32, u32)) {
-    let expect = DateTime::now_date()
-      .with_day(expect.0)
+  fn date(year: i32, month: u32, day: u32) -> DateTime {
+    FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(year, month, day, 0, 0, 0)
       .unwrap()
-      .with_month(expect.1)
-      .unwrap();
+  }
+
+  fn parse(header: &str, reference: DateTime) -> Result<DateTime, DateError> {
+    parse_date(&mut vec![vec![header]].into_iter(), reference)
+  }
+
+  #[rstest]
+  #[case("5 июня", (5, 6))]
+  #[case("Стваыф 5 июля авыфавыф", (5, 7))]
+  #[case("АВыфавыф 24 февраля fdjska sadf", (24, 2))]
+  fn simple(#[case] raw: &str, #[case] expect: (u32, u32)) {
+    let reference = date(2023, 4, 1);
+    assert_eq!(parse(raw, reference), Ok(date(2023, expect.1, expect.0)));
+  }
+
+  #[rstest]
+  #[case("Изменения в расписании на 1 января", date(2023, 12, 31), date(2024, 1, 1))]
+  #[case("Изменения в расписании на 31 декабря", date(2024, 1, 1), date(2023, 12, 31))]
+  #[case("Изменения в расписании на 18 октября", date(2023, 10, 17), date(2023, 10, 18))]
+  fn year_rollover(#[case] raw: &str, #[case] reference: DateTime, #[case] expect: DateTime) {
+    assert_eq!(parse(raw, reference), Ok(expect));
+  }
+
+  #[rstest]
+  // 5 июня 2023 - понедельник, а 5 июня 2024 - среда
+  #[case("Изменения на 5 июня (понедельник)", date(2024, 3, 1), date(2023, 6, 5))]
+  #[case("Изменения на 5 июня, среда", date(2023, 3, 1), date(2024, 6, 5))]
+  #[case("Изменения на 5 июня в среду", date(2024, 3, 1), date(2024, 6, 5))]
+  #[case("Изменения на 5 июня среди групп", date(2023, 3, 1), date(2023, 6, 5))]
+  fn weekday_cross_check(#[case] raw: &str, #[case] reference: DateTime, #[case] expect: DateTime) {
+    assert_eq!(parse(raw, reference), Ok(expect));
+  }
+
+  #[rstest]
+  #[case("среда", Some(Weekday::Wed))]
+  #[case("среду", Some(Weekday::Wed))]
+  #[case("пятницу", Some(Weekday::Fri))]
+  #[case("четверга", Some(Weekday::Thu))]
+  #[case("воскресенье", Some(Weekday::Sun))]
+  #[case("среди", None)]
+  #[case("средство", None)]
+  #[case("субботник", None)]
+  #[case("вторничный", None)]
+  fn weekday(#[case] word: &str, #[case] expect: Option<Weekday>) {
+    assert_eq!(parse_weekday(word), expect);
+  }
+
+  #[rstest]
+  #[case("Изменения на 05.06.2024", date(2024, 6, 5))]
+  #[case("Изменения на 05.06.24", date(2024, 6, 5))]
+  #[case("Изменения на 05.06", date(2024, 6, 5))]
+  #[case("Изменения на 5 июня 2024 г.", date(2024, 6, 5))]
+  fn numeric(#[case] raw: &str, #[case] expect: DateTime) {
+    assert_eq!(parse(raw, date(2024, 6, 1)), Ok(expect));
+  }
 
-    assert_eq!(parse_date(&mut raw.into_iter()), Some(expect));
+  #[rstest]
+  #[case("", DateError::NotFound("".into()))]
+  #[case("FDasfdsa fdsa sadf", DateError::NotFound("FDasfdsa fdsa sadf".into()))]
+  #[case("41234 января", DateError::InvalidDate { day: 41234, month: 1, year: 2023 })]
+  #[case("31 февраля", DateError::InvalidDate { day: 31, month: 2, year: 2023 })]
+  #[case("32.01.2024", DateError::InvalidDate { day: 32, month: 1, year: 2024 })]
+  #[case(
+    "05.06.2024, понедельник",
+    DateError::WeekdayMismatch { date: NaiveDate::from_ymd_opt(2024, 6, 5).unwrap(), weekday: Weekday::Mon }
+  )]
+  fn invalid(#[case] raw: &str, #[case] expect: DateError) {
+    assert_eq!(parse(raw, date(2023, 10, 18)), Err(expect))
   }
 
   #[rstest]
-  #[case(vec![vec!["FDasfdsa fdsa sadf".into()]])]
-  #[case(vec![vec!["41234 января".into()]])]
-  fn invalid(#[case] raw: Vec<Vec<String>>) {
-    assert_eq!(parse_date(&mut raw.into_iter()), None)
+  fn empty_table() {
+    assert_eq!(parse_date(&mut Vec::<Vec<&str>>::new().into_iter(), date(2023, 10, 18)), Err(DateError::NotFound("".into())));
   }
 }
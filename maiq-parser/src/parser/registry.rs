@@ -0,0 +1,262 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::default_lectures::DefaultLectures;
+use super::impls::DeclarativeParser;
+use super::layout::Layout;
+use super::report::ParseReport;
+use super::report::ReportReason;
+use super::table::Table;
+use super::SnapshotParserAgent;
+use crate::snapshot::Snapshot;
+use maiq_time::DateTime;
+
+/// Агент, спрятанный за замыканием: у каждого источника может быть свой
+pub(crate) type AgentFn = Arc<dyn Fn(DateTime, &[Box<str>], &DefaultLectures, Table) -> (Snapshot, ParseReport) + Send + Sync>;
+
+type DetectFn = Arc<dyn Fn(&Table, DateTime) -> f32 + Send + Sync>;
+
+#[derive(Clone)]
+struct RegisteredAgent {
+  name: Box<str>,
+  detect: DetectFn,
+  parse: AgentFn,
+}
+
+/// Агенты, из которых таблицу разбирает самый уверенный, см. [`SnapshotParserAgent::detect`]
+#[derive(Clone, Default)]
+pub struct AgentRegistry {
+  agents: Vec<RegisteredAgent>,
+}
+
+impl AgentRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_agent<P: SnapshotParserAgent + 'static>(mut self) -> Self {
+    self.agents.push(RegisteredAgent {
+      name: P::NAME.into(),
+      detect: Arc::new(P::detect),
+      parse: Arc::new(|fallback_date, group_names, default_lectures, table| {
+        P::new(fallback_date)
+          .with_groups(group_names.iter())
+          .with_default_lectures(default_lectures.clone())
+          .parse(table)
+      }),
+    });
+    self
+  }
+
+  /// [`DeclarativeParser`] с этой раскладкой, уверенность - по [`Layout::detect`]
+  pub fn with_layout(mut self, layout: Layout) -> Self {
+    let layout = Arc::new(layout);
+    let detect = layout.clone();
+    self.agents.push(RegisteredAgent {
+      name: DeclarativeParser::NAME.into(),
+      detect: Arc::new(move |table, now| detect.detect(table, now)),
+      parse: Arc::new(move |fallback_date, group_names, default_lectures, table| {
+        DeclarativeParser::new(fallback_date)
+          .with_layout(layout.clone())
+          .with_groups(group_names.iter())
+          .with_default_lectures(default_lectures.clone())
+          .parse(table)
+      }),
+    });
+    self
+  }
+
+  /// Агенты `other` после своих
+  pub fn chain(mut self, other: AgentRegistry) -> Self {
+    self.agents.extend(other.agents);
+    self
+  }
+
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.agents.iter().map(|agent| agent.name.as_ref())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.agents.is_empty()
+  }
+
+  /// Имена агентов и их уверенность по убыванию, при равенстве - в порядке регистрации
+  pub fn rank(&self, table: &Table, now: DateTime) -> Vec<(&str, f32)> {
+    self
+      .ranked(table, now)
+      .into_iter()
+      .map(|(agent, confidence)| (agent.name.as_ref(), confidence))
+      .collect()
+  }
+
+  fn ranked(&self, table: &Table, now: DateTime) -> Vec<(&RegisteredAgent, f32)> {
+    let mut ranked = self
+      .agents
+      .iter()
+      .map(|agent| (agent, (agent.detect)(table, now)))
+      .collect::<Vec<_>>();
+    ranked.sort_by(|(_, lhs), (_, rhs)| rhs.total_cmp(lhs));
+    ranked
+  }
+
+  /// Снапшот первого агента с неподозрительным результатом, иначе самого уверенного
+  pub(crate) fn parse(
+    &self,
+    fallback_date: DateTime,
+    group_names: &[Box<str>],
+    default_lectures: &DefaultLectures,
+    table: Table,
+  ) -> (Snapshot, ParseReport) {
+    let mut primary = None;
+    for (idx, (agent, confidence)) in self.ranked(&table, fallback_date).into_iter().enumerate() {
+      if idx > 0 && confidence <= 0.0 {
+        break;
+      }
+
+      let (snapshot, report) = (agent.parse)(fallback_date, group_names, default_lectures, table.clone());
+      let snapshot = snapshot.with_agent(agent.name.clone());
+      match suspicion(&snapshot, &report) {
+        None if idx == 0 => return (snapshot, report),
+        None => {
+          info!(target: "parser", "falling back to agent {} (confidence {:.2})", agent.name, confidence);
+          return (snapshot, report);
+        }
+        Some(reason) => {
+          warn!(target: "parser", "agent {} (confidence {:.2}): {}", agent.name, confidence, reason);
+          primary.get_or_insert((snapshot, report));
+        }
+      }
+    }
+
+    primary.unwrap_or_else(|| {
+      warn!(target: "parser", "no agents registered");
+      (Snapshot::new(fallback_date, vec![]), ParseReport::new())
+    })
+  }
+}
+
+/// Почему снапшоту не стоит верить
+fn suspicion(snapshot: &Snapshot, report: &ParseReport) -> Option<&'static str> {
+  if snapshot.groups().len() == 0 {
+    return Some("no groups");
+  }
+  if report
+    .entries()
+    .any(|entry| matches!(entry.reason(), ReportReason::FallbackDate { .. }))
+  {
+    return Some("no date");
+  }
+
+  let lectures = snapshot.groups().map(|group| group.lectures().len()).sum::<usize>();
+  let skipped = report.entries().filter(|entry| entry.is_skipped()).count();
+  (skipped > lectures).then_some("more rows skipped than parsed")
+}
+
+impl Debug for AgentRegistry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_list().entries(self.names()).finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::impls::SnapshotParser4;
+  use maiq_time::*;
+
+  /// Всегда уверен и всегда возвращает пустой снапшот
+  struct Broken(DateTime);
+
+  impl SnapshotParserAgent for Broken {
+    const NAME: &'static str = "broken";
+
+    fn detect(_: &Table, _: DateTime) -> f32 {
+      1.0
+    }
+
+    fn new(fallback_date: DateTime) -> Self {
+      Self(fallback_date)
+    }
+
+    fn with_groups<S: AsRef<str>, I: Iterator<Item = S>>(self, _: I) -> Self {
+      self
+    }
+
+    fn with_default_lectures(self, _: DefaultLectures) -> Self {
+      self
+    }
+
+    fn parse(self, _: Table) -> (Snapshot, ParseReport) {
+      (Snapshot::new(self.0, vec![]), ParseReport::new())
+    }
+  }
+
+  fn reference() -> DateTime {
+    FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, 16, 9, 0, 0)
+      .unwrap()
+  }
+
+  fn table() -> Table {
+    Table::from_text_rows(vec![
+      vec!["Изменения в расписании на 18 октября"],
+      vec!["Группа", "№", "Что", "Где"],
+      vec!["Ир3-21", "1", "История", "102"],
+    ])
+  }
+
+  fn parse(registry: &AgentRegistry) -> (Snapshot, ParseReport) {
+    let groups = ["Ир3-21".into()];
+    registry.parse(reference(), &groups, &DefaultLectures::default(), table())
+  }
+
+  #[rstest]
+  fn rank() {
+    let layout = Layout::from_toml("[headers]\norder = ['№']\nlecture = ['Что']").unwrap();
+    let registry = AgentRegistry::new()
+      .with_agent::<SnapshotParser4>()
+      .with_layout(layout);
+    let ranked = registry.rank(&table(), reference());
+    assert_eq!(ranked.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["declarative", "parser4"]);
+    assert!(ranked[0].1 > ranked[1].1);
+
+    let (snapshot, report) = parse(&registry);
+    assert_eq!(snapshot.agent(), Some("declarative"));
+    assert_eq!(snapshot.group("Ир3-21").unwrap().lectures().next().unwrap().order(), Some("1"));
+    assert!(report.is_empty());
+  }
+
+  #[rstest]
+  fn fallback() {
+    let registry = AgentRegistry::new()
+      .with_agent::<Broken>()
+      .with_agent::<SnapshotParser4>();
+    assert_eq!(registry.rank(&table(), reference())[0].0, "broken");
+
+    let (snapshot, _) = parse(&registry);
+    assert_eq!(snapshot.agent(), Some("parser4"));
+    assert_eq!(snapshot.groups().len(), 1);
+  }
+
+  #[rstest]
+  fn declarative_needs_layout() {
+    let registry = AgentRegistry::new()
+      .with_agent::<DeclarativeParser>()
+      .with_agent::<SnapshotParser4>();
+    let ranked = registry.rank(&table(), reference());
+    assert_eq!(ranked.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["parser4", "declarative"]);
+    assert_eq!(ranked[1].1, 0.0);
+    assert_eq!(parse(&registry).0.agent(), Some("parser4"));
+  }
+
+  #[rstest]
+  fn keeps_primary() {
+    let (snapshot, _) = parse(&AgentRegistry::new().with_agent::<Broken>());
+    assert_eq!(snapshot.agent(), Some("broken"));
+    assert_eq!(snapshot.groups().len(), 0);
+
+    let (snapshot, _) = parse(&AgentRegistry::new());
+    assert_eq!((snapshot.agent(), snapshot.groups().len()), (None, 0));
+  }
+}
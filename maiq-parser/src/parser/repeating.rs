@@ -1,153 +1,1126 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::error::excerpt;
 use crate::Error;
+use tokio::sync::broadcast;
 use tokio::sync::RwLock;
-use tokio::time::Interval;
 
+use super::control::ParserHandle;
 use super::default_lectures::DefaultLectures;
+use super::event::*;
+use super::fetch::FetchResponse;
+use super::fetch::Fetcher;
+use super::fetch::Validators;
+use super::registry::AgentRegistry;
+use super::report::ParseReport;
+use super::report::ReportReason;
+use super::schedule::PollSchedule;
+use super::selection::TableSelection;
+use super::source::*;
 use super::SnapshotParserAgent;
-use super::DEFAULT_TIME_BOUNDS;
-use super::GROUP_NAMES;
 
-use crate::parser::table::*;
+use crate::diff::SnapshotDiff;
 use crate::snapshot::*;
-use crate::utils::time::*;
+use crate::utils::hash::StableHasher;
+use maiq_time::*;
 
+use encoding_rs::Encoding;
 use url::Url;
 
-type SnapshotParseResult = Result<Option<(Snapshot, Changes)>, Error>;
-type SnapshotUpdateCallback = Box<dyn Fn(SnapshotParseResult, SnapshotParseResult) + Send>;
+/// Сколько дней до самой поздней известной даты хранятся снапшоты
+const KEEP_DAYS: i64 = 14;
+
+type SnapshotParseResult = Result<Option<(Snapshot, SnapshotDiff, ParseReport)>, Error>;
+
+/// Сколько событий хранится для отстающих подписчиков
+const EVENTS_CAPACITY: usize = 64;
 
 pub struct RepeatingSnapshotParser<P: SnapshotParserAgent + Send + Sync + 'static> {
   parser: Arc<RwLock<SnapshotParser<P>>>,
-  interval: Interval,
-  time_bounds: std::ops::Range<u32>,
-  callback: Option<SnapshotUpdateCallback>,
+  schedule: PollSchedule,
+  events: broadcast::Sender<ParserEvent>,
+  failures: HashMap<Source, u32>,
+  handle: ParserHandle,
 }
 
 impl<P: SnapshotParserAgent + Send + Sync + 'static> RepeatingSnapshotParser<P> {
   pub fn new(parser: Arc<RwLock<SnapshotParser<P>>>) -> Self {
+    Self::with_interval(parser, Duration::from_secs(60 * 5))
+  }
+
+  /// Каждый день в [`super::DEFAULT_TIME_BOUNDS`] с постоянным интервалом, см. [`PollSchedule::new`]
+  pub fn with_interval(parser: Arc<RwLock<SnapshotParser<P>>>, interval: Duration) -> Self {
+    Self::with_schedule(parser, PollSchedule::new(interval))
+  }
+
+  pub fn with_schedule(parser: Arc<RwLock<SnapshotParser<P>>>, schedule: PollSchedule) -> Self {
     Self {
-      interval: tokio::time::interval(Duration::from_secs(60 * 5)),
       parser,
-      time_bounds: DEFAULT_TIME_BOUNDS,
-      callback: None,
+      schedule,
+      events: broadcast::channel(EVENTS_CAPACITY).0,
+      failures: HashMap::new(),
+      handle: ParserHandle::new(),
     }
   }
 
-  pub fn with_interval(parser: Arc<RwLock<SnapshotParser<P>>>, interval: Duration) -> Self {
-    Self { interval: tokio::time::interval(interval), parser, time_bounds: DEFAULT_TIME_BOUNDS, callback: None }
+  /// Одинаковые часы опроса на каждый день, см. [`PollSchedule::with_hours`]
+  pub fn with_time_bounds(self, time_bounds: std::ops::Range<u32>) -> Self {
+    Self { schedule: self.schedule.with_hours(time_bounds), ..self }
   }
 
-  pub fn with_time_bounds(self, time_bounds: std::ops::Range<u32>) -> Self {
-    Self { time_bounds, ..self }
+  /// Например, с токеном, который отменяется при остановке всего приложения
+  pub fn with_handle(self, handle: ParserHandle) -> Self {
+    Self { handle, ..self }
   }
 
-  pub fn with_update_handler(mut self, f: SnapshotUpdateCallback) -> Self {
-    self.callback.replace(f);
-    self
+  pub fn handle(&self) -> ParserHandle {
+    self.handle.clone()
+  }
+
+  /// После `start` подписаться можно через [`RepeatingSnapshotParser::sender`]
+  pub fn subscribe(&self) -> broadcast::Receiver<ParserEvent> {
+    self.events.subscribe()
+  }
+
+  pub fn sender(&self) -> broadcast::Sender<ParserEvent> {
+    self.events.clone()
   }
 
+  /// Работает до [`ParserHandle::shutdown`]
   pub async fn start(mut self) {
-    let mut should_invoke_handler = false;
-    let callback = self
-      .callback
-      .unwrap_or_else(|| Box::from(|t, n| warn!("unused update: {t:?} & {n:?}")));
+    let mut idle_ticks = 0;
+    let mut delay = Duration::ZERO;
+    let token = self.handle.token().clone();
+    let clock = self.parser.read().await.clock().clone();
     loop {
-      self.interval.tick().await;
-      if !self.time_bounds.contains(&DateTime::now().time().hour()) {
-        should_invoke_handler = false;
+      let forced = tokio::select! {
+        biased;
+        _ = token.cancelled() => break,
+        _ = self.handle.refreshed() => true,
+        _ = tokio::time::sleep(delay) => false,
+      };
+
+      let now = clock.now();
+      delay = self.schedule.interval_at(now, idle_ticks);
+      if !forced && self.handle.is_paused() {
+        debug!(target: "parser", "paused; skipping tick");
         continue;
       }
 
-      debug!(target: "parser", "tick!");
-      let today = self.parser.read().await.fetch_today().await;
-      let next = self.parser.read().await.fetch_next().await;
-      let mut parser = self.parser.write().await;
-
-      if let Ok(today) = today.as_ref() {
-        parser.prev_today_snapshot = today.as_ref().map(|t| t.0.clone());
+      if !forced && !self.schedule.is_active(now) {
+        idle_ticks = 0;
+        continue;
       }
 
-      if let Ok(next) = next.as_ref() {
-        parser.prev_next_snapshot = next.as_ref().map(|n| n.0.clone());
+      debug!(target: "parser", "tick! (forced: {})", forced);
+      self.emit(ParserEvent::Tick { at: clock.now() });
+      let results = tokio::select! {
+        biased;
+        _ = token.cancelled() => break,
+        results = async { self.parser.read().await.fetch_all().await } => results,
+      };
+      let events = self.handle_results(results).await;
+      match events
+        .iter()
+        .any(|event| matches!(event, ParserEvent::SnapshotChanged { .. }))
+      {
+        true => idle_ticks = 0,
+        false => idle_ticks += 1,
       }
-
-      if should_invoke_handler {
-        callback(today, next);
+      for event in events {
+        self.emit(event);
       }
 
-      should_invoke_handler = true;
+      delay = self.schedule.interval_at(now, idle_ticks);
+      debug!(target: "parser", "next tick in {:?}", delay);
+    }
+
+    info!(target: "parser", "repeating parser stopped");
+  }
+
+  /// Первый снапшот источника, для дня которого ничего не известно, приходит с пустым диффом
+  async fn handle_results(&mut self, results: Vec<(Source, SnapshotParseResult)>) -> Vec<ParserEvent> {
+    let mut parser = self.parser.write().await;
+    let mut events = vec![];
+
+    for (source, result) in results {
+      match result {
+        Ok(Some((snapshot, _, report))) => {
+          if let Some(failures) = self.failures.remove(&source).filter(|failures| *failures > 0) {
+            events.push(ParserEvent::Recovered { source: source.clone(), failures });
+          }
+
+          // день мог обновиться раньше в этом же тике
+          let same_day = parser
+            .days
+            .campus_snapshot_for(source.campus(), snapshot.date().date_naive());
+          let diff = match (parser.latest(&source), same_day) {
+            (None, None) => SnapshotDiff::default(),
+            _ => SnapshotDiff::new(same_day, &snapshot),
+          };
+          let changed = parser.latest(&source).map(|latest| latest.id()) != Some(snapshot.id());
+          parser.discover_groups(&snapshot);
+          log_report(&source, &report);
+          parser.set_latest(&source, snapshot.clone(), report.clone());
+          if changed {
+            events.push(ParserEvent::SnapshotChanged { source, snapshot, diff, report });
+          }
+        }
+        Ok(None) => (),
+        Err(error) => {
+          // пустая страница - обычное дело вечером, недоступностью не считается
+          let failures = self.failures.entry(source.clone()).or_default();
+          if !error.can_be_skipped() {
+            *failures += 1;
+          }
+
+          let failures = *failures;
+          let error = Arc::new(error);
+          events.push(ParserEvent::FetchFailed { source: source.clone(), error: error.clone(), failures });
+          if failures == UNAVAILABLE_AFTER && !error.can_be_skipped() {
+            events.push(ParserEvent::SourceUnavailable { source, error });
+          }
+        }
+      }
     }
+
+    events
+  }
+
+  fn emit(&self, event: ParserEvent) {
+    // ошибка только если подписчиков нет
+    _ = self.events.send(event);
+  }
+}
+
+fn log_report(source: &Source, report: &ParseReport) {
+  if report.is_empty() {
+    return;
+  }
+
+  warn!(target: "parser", "{} rows of {} table were skipped or repaired", report.len(), source);
+  for entry in report.entries() {
+    debug!(target: "parser", "{}", entry);
+  }
+}
+
+/// Последняя загруженная страница, чтобы не разбирать её заново
+#[derive(Clone, Debug)]
+pub(crate) struct PageCache {
+  validators: Validators,
+  body_hash: u64,
+  snapshot_id: u64,
+  report: ParseReport,
+}
+
+/// Зарегистрированная страница и последний полученный с неё снапшот
+pub(crate) struct SourceState {
+  pub(crate) source: Source,
+  pub(crate) url: Url,
+  pub(crate) agents: AgentRegistry,
+  pub(crate) latest: Option<Snapshot>,
+  pub(crate) report: Option<ParseReport>,
+}
+
+impl Debug for SourceState {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("SourceState")
+      .field("source", &self.source)
+      .field("url", &self.url)
+      .field("agents", &self.agents)
+      .field("latest", &self.latest.as_ref().map(|snapshot| snapshot.id()))
+      .finish_non_exhaustive()
   }
 }
 
 #[derive(Debug)]
 pub struct SnapshotParser<P: SnapshotParserAgent + Send + Sync> {
   pub(crate) default_lectures: DefaultLectures,
-  pub(crate) today_remote_url: Option<Url>,
-  pub(crate) next_remote_url: Option<Url>,
-  pub(crate) prev_today_snapshot: Option<Snapshot>,
-  pub(crate) prev_next_snapshot: Option<Snapshot>,
+  pub(crate) fetcher: Arc<dyn Fetcher>,
+  pub(crate) encoding: &'static Encoding,
+  pub(crate) table_selection: TableSelection,
+  pub(crate) group_names: Vec<Box<str>>,
+  pub(crate) group_discovery: bool,
+  pub(crate) strict_dates: bool,
+  pub(crate) discovered_groups: Vec<Box<str>>,
+  pub(crate) clock: SharedClock,
+  pub(crate) sources: Vec<SourceState>,
+  pub(crate) pages: Mutex<HashMap<Url, PageCache>>,
+  /// Последние снапшоты по дате, не больше одного на корпус
+  pub(crate) days: BTreeMap<NaiveDate, Vec<Snapshot>>,
   pub(crate) _marker: PhantomData<P>,
 }
 
 impl<P: SnapshotParserAgent + Send + Sync + 'static> SnapshotParser<P> {
+  pub fn groups(&self) -> &[Box<str>] {
+    &self.group_names
+  }
+
+  /// Группы, найденные в таблицах, но отсутствовавшие в изначальном списке
+  pub fn discovered_groups(&self) -> &[Box<str>] {
+    &self.discovered_groups
+  }
+
+  /// Если нашлись новые группы, кеш страниц сбрасывается
+  pub(crate) fn discover_groups(&mut self, snapshot: &Snapshot) {
+    let known = self.discovered_groups.len();
+    for group in snapshot.groups().map(|group| group.name()) {
+      if self
+        .group_names
+        .iter()
+        .chain(self.discovered_groups.iter())
+        .any(|name| &**name == group)
+      {
+        continue;
+      }
+
+      if self.group_discovery {
+        info!(target: "parser", "discovered group {}; adding to group list", group);
+        self.group_names.push(group.into());
+      } else {
+        warn!(target: "parser", "discovered group {} that is not in group list", group);
+      }
+      self.discovered_groups.push(group.into());
+    }
+
+    if self.discovered_groups.len() != known {
+      self.pages.get_mut().unwrap().clear();
+    }
+  }
+
+  pub fn clock(&self) -> &SharedClock {
+    &self.clock
+  }
+
+  pub fn sources(&self) -> impl Iterator<Item = &Source> {
+    self.sources.iter().map(|state| &state.source)
+  }
+
+  /// Корпуса в порядке регистрации, без повторов
+  pub fn campuses(&self) -> Vec<&str> {
+    let mut campuses: Vec<&str> = vec![];
+    for campus in self.sources().map(|source| source.campus()) {
+      if !campuses.contains(&campus) {
+        campuses.push(campus);
+      }
+    }
+    campuses
+  }
+
+  fn state(&self, source: &Source) -> Option<&SourceState> {
+    self.sources.iter().find(|state| state.source == *source)
+  }
+
+  pub fn latest(&self, source: &Source) -> Option<&Snapshot> {
+    self.state(source).and_then(|state| state.latest.as_ref())
+  }
+
+  pub fn latest_report(&self, source: &Source) -> Option<&ParseReport> {
+    self.state(source).and_then(|state| state.report.as_ref())
+  }
+
+  pub(crate) fn set_latest(&mut self, source: &Source, snapshot: Snapshot, report: ParseReport) {
+    if let Some(state) = self.sources.iter_mut().find(|state| state.source == *source) {
+      self.days.remember(snapshot.clone());
+      state.latest = Some(snapshot);
+      state.report = Some(report);
+    }
+  }
+
+  /// Откатывает источник к `notified`, если `snapshot` всё ещё последний
+  pub fn revert(&mut self, snapshot: &Snapshot, notified: Option<Snapshot>) {
+    let Some(state) = self
+      .sources
+      .iter_mut()
+      .find(|state| Some(&state.source) == snapshot.source())
+    else {
+      return;
+    };
+    if state.latest.as_ref().map(|latest| latest.id()) != Some(snapshot.id()) {
+      return;
+    }
+
+    self.days.forget(snapshot);
+    let notified = notified.map(|notified| notified.with_source(state.source.clone()));
+    if let Some(notified) = notified.as_ref() {
+      if self
+        .days
+        .campus_snapshot_for(state.source.campus(), notified.date().date_naive())
+        .is_none()
+      {
+        self.days.remember(notified.clone());
+      }
+    }
+    state.latest = notified;
+    state.report = None;
+  }
+
+  /// Даты, на которые известно расписание, по возрастанию
+  pub fn dates(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+    self.days.keys().copied()
+  }
+
+  /// Снапшот на дату, с какой бы страницы он ни пришёл. Если корпусов несколько - из первого, где он есть
+  pub fn snapshot_for(&self, date: NaiveDate) -> Option<&Snapshot> {
+    self.days.get(&date).and_then(|snapshots| snapshots.first())
+  }
+
+  pub fn campus_snapshot_for(&self, campus: &str, date: NaiveDate) -> Option<&Snapshot> {
+    self.days.campus_snapshot_for(campus, date)
+  }
+
+  /// Снапшоты на даты из диапазона, по возрастанию даты
+  pub fn snapshots_between<R: RangeBounds<NaiveDate>>(&self, range: R) -> impl Iterator<Item = &Snapshot> {
+    self.days.range(range).flat_map(|(_, snapshots)| snapshots.iter())
+  }
+
+  /// Снапшот на сегодня для корпуса по умолчанию, см. [`DEFAULT_CAMPUS`]
   pub fn latest_today(&self) -> Option<&Snapshot> {
-    self.prev_today_snapshot.as_ref()
+    self.latest(&Source::today(DEFAULT_CAMPUS))
   }
 
+  /// Снапшот на завтра для корпуса по умолчанию
   pub fn latest_next(&self) -> Option<&Snapshot> {
-    self.prev_next_snapshot.as_ref()
+    self.latest(&Source::next(DEFAULT_CAMPUS))
   }
 
   pub async fn fetch_today(&self) -> SnapshotParseResult {
-    if let Some(url) = self.today_remote_url.as_ref().cloned() {
-      self
-        .parse_exact(url, self.prev_today_snapshot.as_ref())
-        .await
-        .map(Some)
-    } else {
-      Ok(None)
-    }
+    self.fetch(&Source::today(DEFAULT_CAMPUS)).await
   }
 
   pub async fn fetch_next(&self) -> SnapshotParseResult {
-    if let Some(url) = self.next_remote_url.as_ref().cloned() {
-      self
-        .parse_exact(url, self.prev_next_snapshot.as_ref())
+    self.fetch(&Source::next(DEFAULT_CAMPUS)).await
+  }
+
+  /// `Ok(None)`, если такой источник не зарегистрирован
+  pub async fn fetch(&self, source: &Source) -> SnapshotParseResult {
+    match self.state(source) {
+      Some(state) => self.parse_exact(state).await.map(Some),
+      None => Ok(None),
+    }
+  }
+
+  /// Загружает все источники одновременно
+  pub async fn fetch_all(&self) -> Vec<(Source, SnapshotParseResult)> {
+    let results = futures::future::join_all(self.sources.iter().map(|state| self.parse_exact(state))).await;
+    self
+      .sources
+      .iter()
+      .zip(results)
+      .map(|(state, result)| (state.source.clone(), result.map(Some)))
+      .collect()
+  }
+
+  /// Для неизменившейся страницы - последний снапшот с пустым диффом
+  async fn parse_exact(&self, state: &SourceState) -> Result<(Snapshot, SnapshotDiff, ParseReport), Error> {
+    let url = state.url.clone();
+    let prev = state.latest.as_ref();
+    let cached = prev.and_then(|prev| {
+      let pages = self.pages.lock().unwrap();
+      pages.get(&url).filter(|page| page.snapshot_id == prev.id()).cloned()
+    });
+
+    let (body, validators) = match self
+      .fetcher
+      .fetch(&url, cached.as_ref().map(|page| &page.validators))
+      .await?
+    {
+      FetchResponse::Page { body, validators } => (body, validators),
+      FetchResponse::NotModified => {
+        let (prev, cached) = prev.zip(cached).ok_or(Error::NotModified { url: url.to_string() })?;
+        debug!(target: "parser", "{} is not modified", url);
+        return Ok((prev.clone(), SnapshotDiff::default(), cached.report));
+      }
+    };
+
+    let mut hash = StableHasher::new();
+    hash.write_bytes(&body);
+    let body_hash = hash.finish();
+
+    if let Some((prev, cached)) = prev.zip(cached).filter(|(_, cached)| cached.body_hash == body_hash) {
+      debug!(target: "parser", "{} has the same body", url);
+      self.cache_page(url, PageCache { validators, ..cached.clone() });
+      return Ok((prev.clone(), SnapshotDiff::default(), cached.report));
+    }
+
+    let (html, _, had_errors) = self.encoding.decode(&body);
+    if had_errors {
+      return Err(Error::Encoding { url: url.to_string(), encoding: self.encoding.name(), excerpt: excerpt(&html) });
+    }
+
+    let Some((table, choice)) = self.table_selection.select_html(&html) else {
+      return Err(Error::NoHtmlTable { url: url.to_string(), excerpt: excerpt(&html) });
+    };
+    debug!(target: "parser", "{}: {}", url, choice);
+    let rows = table.text_rows();
+    if rows.len() < 2 {
+      let reason = format!("expected a header and lectures, got {} rows", rows.len());
+      return Err(Error::UnexpectedShape { url: url.to_string(), reason, excerpt: excerpt(&html) });
+    }
+
+    let header = rows[0].join(" ");
+    let (snapshot, report) = state
+      .agents
+      .parse(self.clock.now(), &self.group_names, &self.default_lectures, table);
+    let report = report.with_table(choice);
+    let snapshot = snapshot.with_source(state.source.clone());
+
+    let date_error = report
+      .entries()
+      .filter(|_| self.strict_dates)
+      .find_map(|entry| match entry.reason() {
+        ReportReason::FallbackDate { error, .. } => Some(error.clone()),
+        _ => None,
+      });
+    if let Some(error) = date_error {
+      return Err(Error::Date { url: url.to_string(), error, excerpt: excerpt(&header) });
+    }
+    // все пары отменены - тоже изменение, о нём нужно разослать
+    let report = match snapshot.groups().len() {
+      0 => report.with_entry(&rows[0], ReportReason::NoLectures),
+      _ => report,
+    };
+    // сравниваем с тем же днём, а не с прошлым снапшотом страницы: страница "на завтра" на следующий день становится "на сегодня"
+    let same_day = self
+      .days
+      .campus_snapshot_for(state.source.campus(), snapshot.date().date_naive());
+    let diff = SnapshotDiff::new(same_day, &snapshot);
+    self.cache_page(url, PageCache { validators, body_hash, snapshot_id: snapshot.id(), report: report.clone() });
+    Ok((snapshot, diff, report))
+  }
+
+  fn cache_page(&self, url: Url, page: PageCache) {
+    self.pages.lock().unwrap().insert(url, page);
+  }
+}
+
+pub(crate) trait Days {
+  fn campus_snapshot_for(&self, campus: &str, date: NaiveDate) -> Option<&Snapshot>;
+  fn remember(&mut self, snapshot: Snapshot);
+  fn forget(&mut self, snapshot: &Snapshot);
+}
+
+impl Days for BTreeMap<NaiveDate, Vec<Snapshot>> {
+  fn campus_snapshot_for(&self, campus: &str, date: NaiveDate) -> Option<&Snapshot> {
+    self.get(&date).and_then(|snapshots| {
+      snapshots
+        .iter()
+        .find(|snapshot| snapshot_campus(snapshot) == Some(campus))
+    })
+  }
+
+  /// Заменяет снапшот того же корпуса на ту же дату и забывает дни старше [`KEEP_DAYS`]
+  fn remember(&mut self, snapshot: Snapshot) {
+    let date = snapshot.date().date_naive();
+    let snapshots = self.entry(date).or_default();
+    snapshots.retain(|known| snapshot_campus(known) != snapshot_campus(&snapshot));
+    snapshots.push(snapshot);
+
+    if let Some(last) = self.keys().next_back().copied() {
+      self.retain(|date, _| (last - *date).num_days() <= KEEP_DAYS);
+    }
+  }
+
+  fn forget(&mut self, snapshot: &Snapshot) {
+    let date = snapshot.date().date_naive();
+    if let Some(snapshots) = self.get_mut(&date) {
+      snapshots.retain(|known| known.id() != snapshot.id());
+      if snapshots.is_empty() {
+        self.remove(&date);
+      }
+    }
+  }
+}
+
+fn snapshot_campus(snapshot: &Snapshot) -> Option<&str> {
+  snapshot.source().map(|source| source.campus())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+  use std::time::Duration;
+
+  use tokio::sync::broadcast;
+  use tokio::time::timeout;
+  use url::Url;
+
+  use tokio::sync::RwLock;
+
+  use super::RepeatingSnapshotParser;
+  use crate::diff::GroupDiffKind;
+  use crate::error::DateError;
+  use crate::parser::event::ParserEvent;
+  use crate::parser::fetch::*;
+  use crate::parser::impls::SnapshotParser4;
+  use crate::parser::layout::Layout;
+  use crate::parser::report::ParseReport;
+  use crate::parser::report::ReportReason;
+  use crate::parser::schedule::*;
+  use crate::parser::selection::TableSelection;
+  use crate::parser::source::*;
+  use crate::parser::SnapshotParserBuilder;
+  use crate::snapshot::Id;
+  use crate::snapshot::Snapshot;
+  use crate::Error;
+  use maiq_time::*;
+
+  const TODAY_URL: &str = "https://example.com/today.htm";
+
+  const PAGE: &str = r#"
+    <table>
+      <tr><td>Изменения в расписании на 18 октября</td></tr>
+      <tr><td>Группа</td><td>Пара</td><td>Дисциплина</td><td>Аудитория</td></tr>
+      <tr><td>Ир3-21</td><td>1</td><td>Информационные технологии, Иванов И.Л.</td><td>214</td></tr>
+    </table>"#;
+
+  fn memory<B: Into<Vec<u8>>>(page: B) -> MemoryFetcher {
+    MemoryFetcher::new().with_page(TODAY_URL, page).unwrap()
+  }
+
+  /// Страница "на сегодня" с [`PAGE`] и одной группой
+  #[fixture]
+  fn builder() -> SnapshotParserBuilder {
+    SnapshotParserBuilder::new()
+      .with_today_url(TODAY_URL)
+      .unwrap()
+      .with_groups(["Ир3-21"])
+      .with_fetcher(memory(PAGE))
+      .with_encoding(encoding_rs::UTF_8)
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn fetch_from_memory(builder: SnapshotParserBuilder) {
+    let parser = builder.build::<SnapshotParser4>().unwrap();
+
+    let (snapshot, diff, report) = parser.fetch_today().await.unwrap().unwrap();
+    let lecture = snapshot.group("Ир3-21").unwrap().lectures().next().unwrap();
+    assert_eq!(lecture.name(), "Информационные технологии");
+    assert_eq!(lecture.classroom(), Some("214"));
+    assert!(diff.has_group("Ир3-21"));
+    assert!(report.is_empty());
+    assert!(parser.fetch_next().await.unwrap().is_none());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn multiple_sources() {
+    const OTHER_URL: &str = "https://example.com/1korp/today.htm";
+    let fetcher = MemoryFetcher::new()
+      .with_page(TODAY_URL, PAGE)
+      .unwrap()
+      .with_page(OTHER_URL, PAGE.replace("Ир3-21", "С1-22"))
+      .unwrap();
+    let parser = SnapshotParserBuilder::new()
+      .with_source(Source::today("4korp"), TODAY_URL)
+      .unwrap()
+      .with_source_agent::<SnapshotParser4, _>(Source::today("1korp"), OTHER_URL)
+      .unwrap()
+      .with_groups(["Ир3-21", "С1-22"])
+      .with_fetcher(fetcher)
+      .with_encoding(encoding_rs::UTF_8)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    assert_eq!(parser.campuses(), vec!["4korp", "1korp"]);
+    let results = parser.fetch_all().await;
+    let sources = results
+      .iter()
+      .map(|(source, _)| source.to_string())
+      .collect::<Vec<String>>();
+    assert_eq!(sources, vec!["4korp/today", "1korp/today"]);
+
+    let (snapshot, ..) = results[1].1.as_ref().unwrap().as_ref().unwrap();
+    assert_eq!(snapshot.source(), Some(&Source::today("1korp")));
+    assert!(snapshot.group("С1-22").is_some());
+    assert!(parser.fetch_today().await.unwrap().is_none());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn skip_unchanged_page(builder: SnapshotParserBuilder) {
+    let fetcher = Arc::new(memory(PAGE));
+    let mut parser = builder
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, _, _) = parser.fetch_today().await.unwrap().unwrap();
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), snapshot.clone(), ParseReport::new());
+
+    fetcher.set_page(TODAY_URL, PAGE.replace("214", "301")).unwrap();
+    let (next, diff, _) = parser.fetch_today().await.unwrap().unwrap();
+    assert_ne!(next.id(), snapshot.id());
+    assert!(!diff.is_empty());
+
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), next.clone(), ParseReport::new());
+    let (same, diff, _) = parser.fetch_today().await.unwrap().unwrap();
+    assert_eq!(same.id(), next.id());
+    assert!(diff.is_empty());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn snapshots_by_date(builder: SnapshotParserBuilder) {
+    const NEXT_URL: &str = "https://example.com/tomorrow.htm";
+    let fetcher = Arc::new(MemoryFetcher::new().with_page(NEXT_URL, PAGE).unwrap());
+    let mut parser = builder
+      .with_next_url(NEXT_URL)
+      .unwrap()
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (next, diff, _) = parser.fetch_next().await.unwrap().unwrap();
+    assert!(!diff.is_empty());
+    let date = next.date().date_naive();
+    parser.set_latest(&Source::next(DEFAULT_CAMPUS), next.clone(), ParseReport::new());
+
+    // страница "на завтра" стала страницей "на сегодня", а на завтра выложили другой день
+    fetcher.set_page(TODAY_URL, PAGE).unwrap();
+    fetcher
+      .set_page(NEXT_URL, PAGE.replace("18 октября", "21 октября"))
+      .unwrap();
+    let (today, diff, _) = parser.fetch_today().await.unwrap().unwrap();
+    assert_eq!(today.date().date_naive(), date);
+    assert!(diff.is_empty());
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), today.clone(), ParseReport::new());
+
+    let (later, diff, _) = parser.fetch_next().await.unwrap().unwrap();
+    assert!(diff.has_group("Ир3-21"));
+    parser.set_latest(&Source::next(DEFAULT_CAMPUS), later.clone(), ParseReport::new());
+
+    let later_date = later.date().date_naive();
+    assert_eq!(parser.dates().collect::<Vec<_>>(), vec![date, later_date]);
+    assert_eq!(parser.snapshot_for(date).map(|s| s.id()), Some(today.id()));
+    assert_eq!(parser.campus_snapshot_for(DEFAULT_CAMPUS, later_date).map(|s| s.id()), Some(later.id()));
+    assert!(parser.campus_snapshot_for("1korp", date).is_none());
+    assert_eq!(parser.snapshots_between(date.succ_opt().unwrap()..).count(), 1);
+  }
+
+  #[rstest]
+  #[case("<p>Расписание скоро появится</p>", |err: &Error| matches!(err, Error::NoHtmlTable { excerpt, .. } if excerpt == "Расписание скоро появится"))]
+  #[case("<table><tr><td>Изменения</td></tr></table>", |err: &Error| matches!(err, Error::UnexpectedShape { .. }))]
+  #[case(&PAGE.replace("на 18 октября", ""), |err: &Error| matches!(err, Error::Date { error: DateError::NotFound(_), excerpt, .. } if excerpt == "Изменения в расписании"))]
+  #[tokio::test]
+  async fn errors(#[case] page: &str, #[case] expected: fn(&Error) -> bool, builder: SnapshotParserBuilder) {
+    let parser = builder
+      .with_fetcher(memory(page))
+      .with_strict_dates(true)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let err = parser.fetch_today().await.unwrap_err();
+    assert!(expected(&err), "{:?}", err);
+    assert_eq!(err.url(), Some(TODAY_URL));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn fallback_date(builder: SnapshotParserBuilder) {
+    let parser = builder
+      .with_fetcher(memory(PAGE.replace("на 18 октября", "")))
+      .with_clock(FixedClock::new(&at(2023, 10, 17, 9)))
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, _, report) = parser.fetch_today().await.unwrap().unwrap();
+    assert_eq!(snapshot.date(), at(2023, 10, 17, 9));
+    assert!(snapshot.group("Ир3-21").is_some());
+    assert!(report
+      .entries()
+      .any(|entry| matches!(entry.reason(), ReportReason::FallbackDate { .. })));
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn all_lectures_cancelled(builder: SnapshotParserBuilder) {
+    let fetcher = Arc::new(memory(PAGE));
+    let mut parser = builder
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, ..) = parser.fetch_today().await.unwrap().unwrap();
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), snapshot, ParseReport::new());
+
+    fetcher
+      .set_page(TODAY_URL, PAGE.replace("Информационные технологии, Иванов И.Л.", "Нет"))
+      .unwrap();
+    let (snapshot, diff, report) = parser.fetch_today().await.unwrap().unwrap();
+    assert_eq!(snapshot.groups().len(), 0);
+    assert_eq!(diff.group("Ир3-21").map(|group| group.kind()), Some(GroupDiffKind::Removed));
+    assert!(report
+      .entries()
+      .any(|entry| matches!(entry.reason(), ReportReason::NoLectures)));
+  }
+
+  #[derive(Debug)]
+  struct NotModifiedFetcher;
+
+  impl Fetcher for NotModifiedFetcher {
+    fn fetch<'a>(&'a self, _: &'a Url, validators: Option<&'a Validators>) -> FetchFuture<'a> {
+      Box::pin(async move {
+        match validators {
+          Some(_) => Ok(FetchResponse::NotModified),
+          None => Ok(FetchResponse::Page {
+            body: PAGE.into(),
+            validators: Validators { etag: Some("\"1\"".into()), last_modified: None },
+          }),
+        }
+      })
+    }
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn not_modified(builder: SnapshotParserBuilder) {
+    let mut parser = builder
+      .with_fetcher(NotModifiedFetcher)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, _, _) = parser.fetch_today().await.unwrap().unwrap();
+    parser.set_latest(&Source::today(DEFAULT_CAMPUS), snapshot.clone(), ParseReport::new());
+    let (same, diff, _) = parser.fetch_today().await.unwrap().unwrap();
+    assert_eq!(same.id(), snapshot.id());
+    assert!(diff.is_empty());
+  }
+
+  async fn tick(repeating: &mut RepeatingSnapshotParser<SnapshotParser4>) -> Vec<&'static str> {
+    let results = repeating.parser.read().await.fetch_all().await;
+    let events = repeating.handle_results(results).await;
+    events
+      .into_iter()
+      .map(|event| match event {
+        ParserEvent::Tick { .. } => "tick",
+        ParserEvent::SnapshotChanged { .. } => "changed",
+        ParserEvent::FetchFailed { .. } => "failed",
+        ParserEvent::SourceUnavailable { .. } => "unavailable",
+        ParserEvent::Recovered { .. } => "recovered",
+      })
+      .collect()
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn events(builder: SnapshotParserBuilder) {
+    let fetcher = Arc::new(memory(PAGE));
+    let parser = builder
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+    let mut repeating = RepeatingSnapshotParser::new(Arc::new(RwLock::new(parser)));
+
+    assert_eq!(tick(&mut repeating).await, vec!["changed"]);
+    assert!(tick(&mut repeating).await.is_empty());
+
+    fetcher.remove_page(TODAY_URL).unwrap();
+    assert_eq!(tick(&mut repeating).await, vec!["failed"]);
+    assert_eq!(tick(&mut repeating).await, vec!["failed"]);
+    assert_eq!(tick(&mut repeating).await, vec!["failed", "unavailable"]);
+    assert_eq!(tick(&mut repeating).await, vec!["failed"]);
+
+    fetcher.set_page(TODAY_URL, PAGE.replace("214", "301")).unwrap();
+    assert_eq!(tick(&mut repeating).await, vec!["recovered", "changed"]);
+  }
+
+  /// Источник и пустой ли дифф у каждого изменившегося снапшота за тик
+  async fn diffs(repeating: &mut RepeatingSnapshotParser<SnapshotParser4>) -> Vec<(Source, bool)> {
+    let results = repeating.parser.read().await.fetch_all().await;
+    let events = repeating.handle_results(results).await;
+    events
+      .into_iter()
+      .filter_map(|event| match event {
+        ParserEvent::SnapshotChanged { source, diff, .. } => Some((source, diff.is_empty())),
+        _ => None,
+      })
+      .collect()
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn same_day_from_two_sources(builder: SnapshotParserBuilder) {
+    const NEXT_URL: &str = "https://example.com/tomorrow.htm";
+    let fetcher = Arc::new(memory(PAGE));
+    fetcher
+      .set_page(NEXT_URL, PAGE.replace("18 октября", "19 октября"))
+      .unwrap();
+    let parser = builder
+      .with_next_url(NEXT_URL)
+      .unwrap()
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+    let mut repeating = RepeatingSnapshotParser::new(Arc::new(RwLock::new(parser)));
+    let today = Source::today(DEFAULT_CAMPUS);
+    let next = Source::next(DEFAULT_CAMPUS);
+    assert_eq!(diffs(&mut repeating).await, vec![(today.clone(), true), (next.clone(), true)]);
+
+    // обе страницы в одном тике выложили одно и то же изменённое расписание на 19 октября
+    let page = PAGE.replace("18 октября", "19 октября").replace("214", "301");
+    fetcher.set_page(TODAY_URL, page.clone()).unwrap();
+    fetcher.set_page(NEXT_URL, page).unwrap();
+    assert_eq!(diffs(&mut repeating).await, vec![(today, false), (next, true)]);
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn seed_and_revert(builder: SnapshotParserBuilder) {
+    let fetcher = Arc::new(memory(PAGE));
+    let parser = builder
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+    let parser = Arc::new(RwLock::new(parser));
+    let mut repeating = RepeatingSnapshotParser::new(parser.clone());
+
+    async fn changed(repeating: &mut RepeatingSnapshotParser<SnapshotParser4>) -> Option<(Snapshot, bool)> {
+      let results = repeating.parser.read().await.fetch_all().await;
+      repeating
+        .handle_results(results)
         .await
-        .map(Some)
-    } else {
-      Ok(None)
+        .into_iter()
+        .find_map(|event| match event {
+          ParserEvent::SnapshotChanged { snapshot, diff, .. } => Some((snapshot, diff.is_empty())),
+          _ => None,
+        })
+    }
+
+    // о первом снапшоте не рассылается
+    let (seeded, empty) = changed(&mut repeating).await.unwrap();
+    assert!(empty);
+
+    fetcher.set_page(TODAY_URL, PAGE.replace("214", "301")).unwrap();
+    let (failed, empty) = changed(&mut repeating).await.unwrap();
+    assert!(!empty);
+
+    parser.write().await.revert(&failed, Some(seeded.clone()));
+    assert_eq!(parser.read().await.latest_today().map(|s| s.id()), Some(seeded.id()));
+    let (retried, empty) = changed(&mut repeating).await.unwrap();
+    assert_eq!(retried.id(), failed.id());
+    assert!(!empty);
+    assert!(changed(&mut repeating).await.is_none());
+
+    // снапшот уже не последний
+    parser.write().await.revert(&seeded, None);
+    assert_eq!(parser.read().await.latest_today().map(|s| s.id()), Some(failed.id()));
+  }
+
+  #[rstest]
+  #[case(PAGE, vec![])]
+  #[case(&PAGE.replace("214", "301"), vec!["changed"])]
+  #[tokio::test]
+  async fn changes_survive_restart(#[case] notified_page: &str, #[case] expected: Vec<&str>) {
+    let build = |page: &str, latest: Option<Snapshot>| {
+      let mut builder = builder::default().with_fetcher(memory(page));
+      if let Some(latest) = latest {
+        builder = builder.with_latest_today(latest);
+      }
+      builder.build::<SnapshotParser4>().unwrap()
+    };
+
+    let (notified, ..) = build(notified_page, None).fetch_today().await.unwrap().unwrap();
+    let parser = build(PAGE, Some(notified));
+    let mut repeating = RepeatingSnapshotParser::new(Arc::new(RwLock::new(parser)));
+    assert_eq!(tick(&mut repeating).await, expected);
+    assert!(tick(&mut repeating).await.is_empty());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn next_becomes_today_after_restart(builder: SnapshotParserBuilder) {
+    const NEXT_URL: &str = "https://example.com/tomorrow.htm";
+    let next_page = PAGE.replace("18 октября", "19 октября");
+    let fetcher = Arc::new(memory(PAGE));
+    fetcher.set_page(NEXT_URL, next_page.clone()).unwrap();
+    let parser = builder
+      .with_next_url(NEXT_URL)
+      .unwrap()
+      .with_fetcher(fetcher.clone())
+      .build::<SnapshotParser4>()
+      .unwrap();
+    let today = parser.fetch_today().await.unwrap().unwrap().0;
+    let next = parser.fetch_next().await.unwrap().unwrap().0;
+
+    // после рестарта страница "на завтра" стала страницей "на сегодня", а на завтра выложили новый день
+    fetcher.set_page(TODAY_URL, next_page).unwrap();
+    fetcher
+      .set_page(NEXT_URL, PAGE.replace("18 октября", "20 октября"))
+      .unwrap();
+    let parser = builder::default()
+      .with_next_url(NEXT_URL)
+      .unwrap()
+      .with_fetcher(fetcher)
+      .with_latest_today(today)
+      .with_latest_next(next)
+      .build::<SnapshotParser4>()
+      .unwrap();
+    let mut repeating = RepeatingSnapshotParser::new(Arc::new(RwLock::new(parser)));
+    let expected = vec![(Source::today(DEFAULT_CAMPUS), true), (Source::next(DEFAULT_CAMPUS), false)];
+    assert_eq!(diffs(&mut repeating).await, expected);
+    assert!(diffs(&mut repeating).await.is_empty());
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn control(builder: SnapshotParserBuilder) {
+    let parser = builder.build::<SnapshotParser4>().unwrap();
+    let repeating =
+      RepeatingSnapshotParser::with_interval(Arc::new(RwLock::new(parser)), Duration::from_secs(3600)).with_time_bounds(0..24);
+    let handle = repeating.handle();
+    let mut events = repeating.subscribe();
+    let task = tokio::spawn(repeating.start());
+
+    async fn next_tick(events: &mut broadcast::Receiver<ParserEvent>) {
+      loop {
+        if let ParserEvent::Tick { .. } = timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap() {
+          return;
+        }
+      }
     }
+
+    // первый тик интервала срабатывает сразу
+    next_tick(&mut events).await;
+    handle.pause();
+    handle.refresh_now();
+    next_tick(&mut events).await;
+    assert!(handle.is_paused());
+
+    handle.shutdown();
+    timeout(Duration::from_secs(5), task).await.unwrap().unwrap();
+    assert!(handle.is_shutdown());
+  }
+
+  fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime {
+    FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(year, month, day, hour, 0, 0)
+      .unwrap()
+  }
+
+  #[rstest]
+  #[case(at(2024, 10, 17, 10), (2024, 10, 18))]
+  #[case(at(2022, 10, 17, 10), (2022, 10, 18))]
+  #[case(at(2024, 1, 10, 10), (2023, 10, 18))]
+  #[tokio::test]
+  async fn year_from_clock(#[case] now: DateTime, #[case] expected: (i32, u32, u32), builder: SnapshotParserBuilder) {
+    let parser = builder
+      .with_clock(FixedClock::new(&now))
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, ..) = parser.fetch_today().await.unwrap().unwrap();
+    let (year, month, day) = expected;
+    assert_eq!(snapshot.date().date_naive(), NaiveDate::from_ymd_opt(year, month, day).unwrap());
   }
 
-  async fn parse_exact(&self, url: Url, prev: Option<&Snapshot>) -> Result<(Snapshot, Changes), Error> {
-    let table = self
-      .fetch_table(url)
+  #[rstest]
+  #[tokio::test(start_paused = true)]
+  async fn polling_window(builder: SnapshotParserBuilder) {
+    // 22 октября 2023 - воскресенье
+    let clock = FixedClock::new(&at(2023, 10, 22, 10));
+    let parser = builder.with_clock(clock.clone()).build::<SnapshotParser4>().unwrap();
+    let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+    let schedule = PollSchedule::new(Duration::from_secs(60)).with_windows(vec![PollWindow::new(weekdays, 7..18)]);
+    let repeating = RepeatingSnapshotParser::with_schedule(Arc::new(RwLock::new(parser)), schedule);
+    let handle = repeating.handle();
+    let mut events = repeating.subscribe();
+    let task = tokio::spawn(repeating.start());
+
+    // время тестового рантайма остановлено и перематывается само, так что час проходит мгновенно
+    assert!(timeout(Duration::from_secs(3600), events.recv()).await.is_err());
+
+    clock.set(&at(2023, 10, 23, 10));
+    let event = timeout(Duration::from_secs(120), events.recv())
       .await
-      .map_err(Box::from)?
-      .ok_or(Error::NoHtmlTable)?;
-    let parser = P::new(DateTime::now())
-      .with_groups(GROUP_NAMES.iter())
-      .with_default_lectures(self.default_lectures.clone());
-    let snapshot = parser.parse(table);
-    let changes = prev.changes(Some(&snapshot), &GROUP_NAMES);
-    Ok((snapshot, changes))
-  }
-
-  async fn fetch_table(&self, url: Url) -> Result<Option<Table>, ureq::Error> {
-    let mut reader = ureq::get(url.as_str())
-      .timeout(Duration::from_secs(15))
-      .call()?
-      .into_reader();
-    let mut buf = vec![];
-    reader.read_to_end(&mut buf)?;
-    let html_raw = encoding_rs::WINDOWS_1251.decode(&buf).0;
-
-    Ok(parse_last_table(&html_raw))
+      .unwrap()
+      .unwrap();
+    assert!(matches!(event, ParserEvent::Tick { at: now } if now == at(2023, 10, 23, 10)));
+
+    clock.set(&at(2023, 10, 23, 18));
+    while let Ok(event) = timeout(Duration::from_secs(120), events.recv()).await {
+      assert!(!matches!(event.unwrap(), ParserEvent::Tick { .. }));
+    }
+
+    handle.shutdown();
+    task.await.unwrap();
+  }
+
+  #[rstest]
+  #[tokio::test]
+  async fn layout(builder: SnapshotParserBuilder) {
+    let page = PAGE.replace("<td>Пара</td>", "<td>№</td>").replace(
+      "<td>1</td><td>Информационные технологии, Иванов И.Л.</td>",
+      "<td>1</td><td>Информационные технологии [Иванов И.Л.]</td>",
+    );
+    let layout = Layout::from_toml("teacher = '^(?P<name>.+?) \\[(?P<teacher>.+)\\]$'\n[headers]\norder = ['№']").unwrap();
+    let parser = builder
+      .with_fetcher(memory(page))
+      .with_layout(layout)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, ..) = parser.fetch_today().await.unwrap().unwrap();
+    let lecture = snapshot.group("Ир3-21").unwrap().lectures().next().unwrap();
+    assert_eq!((lecture.name(), lecture.teacher()), ("Информационные технологии", Some("Иванов И.Л.")));
+  }
+
+  #[rstest]
+  #[case(TableSelection::Largest, Some((vec![0], "Информационные технологии")))]
+  #[case(TableSelection::Last, None)]
+  #[case(TableSelection::Concat, Some((vec![0, 1], "Информационные технологии")))]
+  #[tokio::test]
+  async fn table_selection(
+    #[case] selection: TableSelection,
+    #[case] expected: Option<(Vec<usize>, &str)>,
+    builder: SnapshotParserBuilder,
+  ) {
+    let page = format!("{}<table><tr><td>Заместитель директора по УР</td></tr></table>", PAGE);
+    let parser = builder
+      .with_fetcher(memory(page))
+      .with_table_selection(selection)
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    match (parser.fetch_today().await, expected) {
+      (Ok(Some((snapshot, _, report))), Some((selected, name))) => {
+        assert_eq!(report.table().map(|table| (table.tables, table.selected.clone())), Some((2, selected)));
+        assert_eq!(snapshot.group("Ир3-21").unwrap().lectures().next().unwrap().name(), name);
+      }
+      (Err(err), None) => assert!(matches!(err, Error::UnexpectedShape { .. })),
+      (result, expected) => {
+        panic!("{:?}, expected {:?}", result.map(|result| result.map(|(snapshot, ..)| snapshot.id())), expected)
+      }
+    }
+  }
+
+  #[rstest]
+  #[case(false, vec!["Ир3-21"])]
+  #[case(true, vec!["Ир3-21", "Ир3-23"])]
+  #[tokio::test]
+  async fn discover_groups(#[case] enabled: bool, #[case] expected: Vec<&str>, builder: SnapshotParserBuilder) {
+    let page = PAGE.replace("</table>", "<tr><td>Ир3-23</td><td>2</td><td>История</td><td>102</td></tr></table>");
+    let mut parser = builder
+      .with_group_discovery(enabled)
+      .with_fetcher(memory(page))
+      .build::<SnapshotParser4>()
+      .unwrap();
+
+    let (snapshot, _, _) = parser.fetch_today().await.unwrap().unwrap();
+    assert!(!parser.pages.lock().unwrap().is_empty());
+    parser.discover_groups(&snapshot);
+    assert!(parser.pages.lock().unwrap().is_empty());
+    parser.discover_groups(&snapshot);
+    assert_eq!(parser.groups().iter().map(|g| &**g).collect::<Vec<&str>>(), expected);
+    assert_eq!(parser.discovered_groups(), &[Box::from("Ир3-23")]);
   }
 }
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+use std::slice::Iter;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::selection::TableChoice;
+use crate::error::DateError;
+
+/// Строки таблицы, которые парсер пропустил или исправил, и какая таблица страницы разбиралась
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct ParseReport {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  table: Option<TableChoice>,
+  entries: Vec<ReportEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ReportEntry {
+  cells: Vec<String>,
+  reason: ReportReason,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportReason {
+  /// Не нашлось даты, использована запасная. Со строгими датами такая страница - ошибка, см.
+  /// [`super::SnapshotParserBuilder::with_strict_dates`]
+  FallbackDate { fallback: String, error: DateError },
+  /// Пара до первой строки с группой, пропущена
+  NoGroup,
+  /// Группы нет в списке известных, пара оставлена
+  UnknownGroup { name: String },
+  /// Номер пары взят из предыдущей строки
+  PreviousOrder { order: String },
+  /// Номер пары не распознан и взять его неоткуда, пара оставлена без номера
+  BadOrder,
+  /// Нет названия пары, строка пропущена
+  NoLectureName,
+  /// Пара "по расписанию" заменена на пару из основного расписания
+  DefaultLecture { name: String },
+  /// Пара "по расписанию", но в основном расписании её нет
+  NoDefaultLecture,
+  /// В таблице не осталось ни одной пары, например, все отменены
+  NoLectures,
+}
+
+impl ParseReport {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push<S: AsRef<str>>(&mut self, cells: &[S], reason: ReportReason) {
+    let cells = cells.iter().map(|cell| cell.as_ref().to_string()).collect();
+    self.entries.push(ReportEntry { cells, reason });
+  }
+
+  pub fn with_entry<S: AsRef<str>>(mut self, cells: &[S], reason: ReportReason) -> Self {
+    self.push(cells, reason);
+    self
+  }
+
+  pub fn with_table(self, table: TableChoice) -> Self {
+    Self { table: Some(table), ..self }
+  }
+
+  pub fn table(&self) -> Option<&TableChoice> {
+    self.table.as_ref()
+  }
+
+  pub fn entries(&self) -> Iter<'_, ReportEntry> {
+    self.entries.iter()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+impl ReportEntry {
+  pub fn cells(&self) -> &[String] {
+    &self.cells
+  }
+
+  pub fn reason(&self) -> &ReportReason {
+    &self.reason
+  }
+
+  /// Строка не попала в снапшот
+  pub fn is_skipped(&self) -> bool {
+    matches!(self.reason, ReportReason::NoGroup | ReportReason::NoLectureName)
+  }
+}
+
+impl Display for ParseReport {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Some(table) = self.table.as_ref() {
+      writeln!(f, "{}", table)?;
+    }
+    for entry in self.entries() {
+      writeln!(f, "{}", entry)?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for ReportEntry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} [{}]", self.reason, self.cells.join(" | "))
+  }
+}
+
+impl Display for ReportReason {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::FallbackDate { fallback, error } => write!(f, "нет даты ({}), взята {}", error, fallback),
+      Self::NoGroup => write!(f, "пропущено, нет группы"),
+      Self::UnknownGroup { name } => write!(f, "неизвестная группа {}", name),
+      Self::PreviousOrder { order } => write!(f, "номер пары {} взят из предыдущей строки", order),
+      Self::BadOrder => write!(f, "не удалось определить номер пары"),
+      Self::NoLectureName => write!(f, "пропущено, нет названия пары"),
+      Self::DefaultLecture { name } => write!(f, "по расписанию: {}", name),
+      Self::NoDefaultLecture => write!(f, "по расписанию, но в основном расписании пары нет"),
+      Self::NoLectures => write!(f, "на странице нет ни одной пары"),
+    }
+  }
+}
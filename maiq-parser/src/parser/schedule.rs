@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::Error as _;
+use serde::de::Unexpected;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+use crate::Error;
+use maiq_time::*;
+
+use super::DEFAULT_TIME_BOUNDS;
+
+const DEFAULT_INTERVAL_SECS: u64 = 60 * 5;
+
+/// Когда и как часто загружать страницы, например:
+/// ```toml
+/// interval = 300
+/// holidays = ["2023-11-06", "2023-12-31"]
+///
+/// [[windows]]
+/// weekdays = ["mon", "tue", "wed", "thu", "fri"]
+/// hours = [7, 20]
+///
+/// [[windows]]
+/// weekdays = ["sat"]
+/// hours = [7, 14]
+///
+/// # вечером чаще
+/// [evening]
+/// from = 15
+/// interval = 60
+///
+/// # после 6 тиков без изменений интервал удваивается, но не больше 30 минут
+/// [backoff]
+/// after = 6
+/// max = 1800
+/// ```
+/// Интервалы - в секундах, часы окна - `[с, до)`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PollSchedule {
+  #[serde(default = "default_interval", deserialize_with = "positive")]
+  interval: u64,
+  #[serde(default = "default_windows")]
+  windows: Vec<PollWindow>,
+  #[serde(default)]
+  holidays: BTreeSet<NaiveDate>,
+  #[serde(default)]
+  evening: Option<EveningPolling>,
+  #[serde(default)]
+  backoff: Option<Backoff>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PollWindow {
+  pub weekdays: Vec<Weekday>,
+  pub hours: (u32, u32),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct EveningPolling {
+  pub from: u32,
+  #[serde(deserialize_with = "positive")]
+  pub interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Backoff {
+  pub after: u32,
+  pub max: u64,
+}
+
+fn default_interval() -> u64 {
+  DEFAULT_INTERVAL_SECS
+}
+
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+  match u64::deserialize(deserializer)? {
+    0 => Err(D::Error::invalid_value(Unexpected::Unsigned(0), &"a positive interval in seconds")),
+    secs => Ok(secs),
+  }
+}
+
+/// Не меньше секунды
+fn whole_secs(interval: Duration) -> u64 {
+  interval.as_secs().max(1)
+}
+
+fn default_windows() -> Vec<PollWindow> {
+  let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+  vec![PollWindow::new(weekdays, DEFAULT_TIME_BOUNDS)]
+}
+
+impl Default for PollSchedule {
+  fn default() -> Self {
+    Self::new(Duration::from_secs(DEFAULT_INTERVAL_SECS))
+  }
+}
+
+impl PollSchedule {
+  /// Каждый день в [`DEFAULT_TIME_BOUNDS`] с постоянным интервалом
+  pub fn new(interval: Duration) -> Self {
+    Self { interval: whole_secs(interval), windows: default_windows(), holidays: BTreeSet::new(), evening: None, backoff: None }
+  }
+
+  pub fn from_toml(raw: &str) -> Result<Self, Error> {
+    toml::from_str(raw).map_err(Error::ScheduleToml)
+  }
+
+  pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    let path = path.as_ref();
+    Self::from_toml(&std::fs::read_to_string(path).map_err(|err| Error::file(path, err))?)
+  }
+
+  /// Одинаковые часы на каждый день
+  pub fn with_hours(self, hours: Range<u32>) -> Self {
+    let windows = default_windows()
+      .into_iter()
+      .map(|window| PollWindow::new(window.weekdays, hours.clone()))
+      .collect();
+    Self { windows, ..self }
+  }
+
+  /// Заменяет все окна
+  pub fn with_windows(self, windows: Vec<PollWindow>) -> Self {
+    Self { windows, ..self }
+  }
+
+  pub fn with_holidays<I: IntoIterator<Item = NaiveDate>>(mut self, holidays: I) -> Self {
+    self.holidays.extend(holidays);
+    self
+  }
+
+  pub fn with_evening(self, from: u32, interval: Duration) -> Self {
+    Self { evening: Some(EveningPolling { from, interval: whole_secs(interval) }), ..self }
+  }
+
+  pub fn with_backoff(self, after: u32, max: Duration) -> Self {
+    Self { backoff: Some(Backoff { after, max: whole_secs(max) }), ..self }
+  }
+
+  pub fn is_holiday(&self, date: NaiveDate) -> bool {
+    self.holidays.contains(&date)
+  }
+
+  /// Нужно ли опрашивать страницы в этот момент
+  pub fn is_active(&self, now: DateTime) -> bool {
+    !self.is_holiday(now.date_naive()) && self.windows.iter().any(|window| window.contains(now))
+  }
+
+  /// Сколько ждать до следующего тика после `idle_ticks` тиков без изменений
+  pub fn interval_at(&self, now: DateTime, idle_ticks: u32) -> Duration {
+    let evening = self.evening.filter(|evening| now.hour() >= evening.from);
+    let base = match evening {
+      Some(evening) => evening.interval,
+      None => self.interval,
+    };
+
+    let secs = match self.backoff {
+      Some(backoff) if evening.is_none() && idle_ticks > backoff.after => {
+        let factor = 2u64.saturating_pow((idle_ticks - backoff.after).min(32));
+        base.saturating_mul(factor).min(backoff.max.max(base))
+      }
+      _ => base,
+    };
+    Duration::from_secs(secs)
+  }
+}
+
+impl PollWindow {
+  pub fn new<I: IntoIterator<Item = Weekday>>(weekdays: I, hours: Range<u32>) -> Self {
+    Self { weekdays: weekdays.into_iter().collect(), hours: (hours.start, hours.end) }
+  }
+
+  pub fn contains(&self, now: DateTime) -> bool {
+    self.weekdays.contains(&now.weekday()) && (self.hours.0..self.hours.1).contains(&now.hour())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SCHEDULE: &str = r#"
+    interval = 300
+    holidays = ["2023-11-06"]
+
+    [[windows]]
+    weekdays = ["mon", "tue", "wed", "thu", "fri"]
+    hours = [7, 20]
+
+    [[windows]]
+    weekdays = ["sat"]
+    hours = [7, 14]
+
+    [evening]
+    from = 15
+    interval = 60
+
+    [backoff]
+    after = 2
+    max = 1000
+  "#;
+
+  fn at(day: u32, hour: u32) -> DateTime {
+    FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 11, day, hour, 0, 0)
+      .unwrap()
+  }
+
+  #[rstest]
+  #[case(at(7, 10), true)]
+  #[case(at(7, 6), false)]
+  #[case(at(7, 20), false)]
+  #[case(at(11, 13), true)]
+  #[case(at(11, 15), false)]
+  #[case(at(12, 10), false)]
+  #[case(at(6, 10), false)]
+  fn active(#[case] now: DateTime, #[case] expected: bool) {
+    let schedule = PollSchedule::from_toml(SCHEDULE).unwrap();
+    assert_eq!(schedule.is_active(now), expected);
+  }
+
+  #[rstest]
+  #[case(at(7, 10), 0, 300)]
+  #[case(at(7, 16), 0, 60)]
+  #[case(at(7, 10), 2, 300)]
+  #[case(at(7, 10), 3, 600)]
+  #[case(at(7, 10), 4, 1000)]
+  #[case(at(7, 16), 4, 60)]
+  #[case(at(7, 10), 100, 1000)]
+  fn interval(#[case] now: DateTime, #[case] idle_ticks: u32, #[case] expected: u64) {
+    let schedule = PollSchedule::from_toml(SCHEDULE).unwrap();
+    assert_eq!(schedule.interval_at(now, idle_ticks), Duration::from_secs(expected));
+  }
+
+  #[rstest]
+  #[case("interval = 0")]
+  #[case("[evening]\nfrom = 15\ninterval = 0")]
+  fn zero_interval(#[case] raw: &str) {
+    let err = PollSchedule::from_toml(raw).unwrap_err();
+    assert!(err.to_string().contains("a positive interval"), "{}", err);
+  }
+
+  #[rstest]
+  fn sub_second_interval() {
+    let schedule = PollSchedule::new(Duration::from_millis(500))
+      .with_evening(15, Duration::ZERO)
+      .with_backoff(0, Duration::ZERO);
+    assert_eq!(schedule.interval_at(at(12, 10), 0), Duration::from_secs(1));
+    assert_eq!(schedule.interval_at(at(12, 16), 0), Duration::from_secs(1));
+    assert_eq!(schedule.interval_at(at(12, 10), 100), Duration::from_secs(1));
+  }
+
+  #[rstest]
+  fn defaults() {
+    let schedule = PollSchedule::from_toml("").unwrap();
+    assert_eq!(schedule, PollSchedule::default());
+    assert!(schedule.is_active(at(12, 10)));
+    assert!(!schedule.is_active(at(12, 18)));
+    assert_eq!(schedule.interval_at(at(12, 10), 100), Duration::from_secs(300));
+  }
+}
@@ -0,0 +1,182 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::table::parse_all_tables;
+use super::table::Table;
+
+/// Какую из непустых таблиц страницы отдавать агенту: `last`, `first`, `largest`, `concat` или `caption:<выражение>`
+#[derive(Clone, Default, Debug)]
+pub enum TableSelection {
+  /// Последнюю
+  #[default]
+  Last,
+  First,
+  /// С наибольшим числом заполненных ячеек, при равенстве - последнюю
+  Largest,
+  /// Первую, у которой `<caption>` или первая строка совпадает с выражением
+  Caption(Regex),
+  /// Все таблицы одна за другой, единственный вариант для даты в отдельной таблице над парами
+  Concat,
+}
+
+/// Что выбрала [`TableSelection`], попадает в [`super::report::ParseReport`]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TableChoice {
+  pub strategy: String,
+  /// Сколько всего таблиц на странице
+  pub tables: usize,
+  /// Номера выбранных таблиц с нуля, по порядку на странице
+  pub selected: Vec<usize>,
+}
+
+impl TableSelection {
+  /// Таблица со страницы и что было выбрано. `None`, если подходящих таблиц нет
+  pub fn select_html(&self, html: &str) -> Option<(Table, TableChoice)> {
+    self.select(parse_all_tables(html)?)
+  }
+
+  pub fn select(&self, tables: Vec<Table>) -> Option<(Table, TableChoice)> {
+    let count = tables.len();
+    let mut candidates = tables
+      .into_iter()
+      .enumerate()
+      .filter(|(_, table)| !table.is_empty())
+      .collect::<Vec<(usize, Table)>>();
+
+    let selected = match self {
+      Self::Last => candidates.pop().into_iter().collect(),
+      Self::First => candidates.into_iter().take(1).collect(),
+      Self::Largest => candidates
+        .into_iter()
+        .max_by_key(|(_, table)| table.filled_cells())
+        .into_iter()
+        .collect(),
+      Self::Caption(regex) => candidates
+        .into_iter()
+        .find(|(_, table)| regex.is_match(&caption_or_first_row(table)))
+        .into_iter()
+        .collect(),
+      Self::Concat => candidates,
+    };
+    if selected.is_empty() {
+      return None;
+    }
+
+    let choice =
+      TableChoice { strategy: self.to_string(), tables: count, selected: selected.iter().map(|(idx, _)| *idx).collect() };
+    let mut selected = selected.into_iter().map(|(_, table)| table);
+    let first = selected.next()?;
+    let table = selected.fold(first, |mut table, next| {
+      table.caption = table.caption.or(next.caption);
+      table.rows.extend(next.rows);
+      table
+    });
+    Some((table, choice))
+  }
+}
+
+fn caption_or_first_row(table: &Table) -> String {
+  match table.caption.as_ref() {
+    Some(caption) => caption.clone(),
+    None => table.text_rows().into_iter().next().unwrap_or_default().join(" "),
+  }
+}
+
+impl Display for TableSelection {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Last => write!(f, "last"),
+      Self::First => write!(f, "first"),
+      Self::Largest => write!(f, "largest"),
+      Self::Caption(regex) => write!(f, "caption:{}", regex),
+      Self::Concat => write!(f, "concat"),
+    }
+  }
+}
+
+impl FromStr for TableSelection {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim() {
+      "last" => Ok(Self::Last),
+      "first" => Ok(Self::First),
+      "largest" => Ok(Self::Largest),
+      "concat" => Ok(Self::Concat),
+      s => match s.strip_prefix("caption:") {
+        Some(regex) => Regex::new(regex)
+          .map(Self::Caption)
+          .map_err(|err| format!("invalid caption regex: {}", err)),
+        None => Err(format!("invalid table selection `{}`", s)),
+      },
+    }
+  }
+}
+
+impl Display for TableChoice {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let selected = self
+      .selected
+      .iter()
+      .map(|idx| (idx + 1).to_string())
+      .collect::<Vec<String>>()
+      .join(", ");
+    write!(f, "таблица {} из {} ({})", selected, self.tables, self.strategy)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const PAGE: &str = r#"
+    <table><tr><td>Изменения в расписании на 24 октября</td></tr></table>
+    <table></table>
+    <table>
+      <caption>Пары</caption>
+      <tr><td>Ир3-21</td><td>1</td><td>История</td></tr>
+      <tr><td>Ир1-21</td><td>2</td><td>Математика</td></tr>
+    </table>
+    <table><tr><td>Заместитель директора по УР</td></tr></table>
+  "#;
+
+  #[rstest]
+  #[case("last", vec![3], "Заместитель директора по УР")]
+  #[case("first", vec![0], "Изменения в расписании на 24 октября")]
+  #[case("largest", vec![2], "Ир3-21")]
+  #[case("caption:^Пары$", vec![2], "Ир3-21")]
+  #[case("caption:(?i)изменения", vec![0], "Изменения в расписании на 24 октября")]
+  #[case("concat", vec![0, 2, 3], "Изменения в расписании на 24 октября")]
+  fn select(#[case] selection: &str, #[case] selected: Vec<usize>, #[case] first_cell: &str) {
+    let selection = selection.parse::<TableSelection>().unwrap();
+    let (table, choice) = selection.select_html(PAGE).unwrap();
+    assert_eq!(choice, TableChoice { strategy: selection.to_string(), tables: 4, selected });
+    assert_eq!(table.rows[0][0].text, first_cell);
+  }
+
+  #[rstest]
+  fn concat() {
+    let (table, choice) = TableSelection::Concat.select_html(PAGE).unwrap();
+    assert_eq!(table.text_rows().len(), 4);
+    assert_eq!(table.caption.as_deref(), Some("Пары"));
+    assert_eq!(choice.to_string(), "таблица 1, 3, 4 из 4 (concat)");
+  }
+
+  #[rstest]
+  fn nothing_selected() {
+    let selection = "caption:^Нет такой$".parse::<TableSelection>().unwrap();
+    assert!(selection.select_html(PAGE).is_none());
+    assert!(TableSelection::Largest.select_html("<p>Нет таблиц</p>").is_none());
+  }
+
+  #[rstest]
+  #[case("biggest")]
+  #[case("caption:(")]
+  fn invalid(#[case] raw: &str) {
+    assert!(raw.parse::<TableSelection>().is_err());
+  }
+}
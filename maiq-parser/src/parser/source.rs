@@ -0,0 +1,98 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Корпус для источников, добавленных через `with_today_url` и `with_next_url`
+pub const DEFAULT_CAMPUS: &str = "default";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceDay {
+  Today,
+  Next,
+}
+
+/// Страница с расписанием: корпус и день. В строковом виде - `4korp/today`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Source {
+  campus: Box<str>,
+  day: SourceDay,
+}
+
+impl Source {
+  pub fn new<S: AsRef<str>>(campus: S, day: SourceDay) -> Self {
+    Self { campus: campus.as_ref().into(), day }
+  }
+
+  pub fn today<S: AsRef<str>>(campus: S) -> Self {
+    Self::new(campus, SourceDay::Today)
+  }
+
+  pub fn next<S: AsRef<str>>(campus: S) -> Self {
+    Self::new(campus, SourceDay::Next)
+  }
+
+  pub fn campus(&self) -> &str {
+    &self.campus
+  }
+
+  pub fn day(&self) -> SourceDay {
+    self.day
+  }
+}
+
+impl SourceDay {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SourceDay::Today => "today",
+      SourceDay::Next => "next",
+    }
+  }
+}
+
+impl Display for Source {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.campus, self.day.as_str())
+  }
+}
+
+impl FromStr for Source {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (campus, day) = s.rsplit_once('/').ok_or_else(|| format!("invalid source `{}`", s))?;
+    let day = match day {
+      "today" => SourceDay::Today,
+      "next" => SourceDay::Next,
+      _ => return Err(format!("invalid source day `{}`", day)),
+    };
+
+    match campus.is_empty() {
+      true => Err(format!("invalid source `{}`", s)),
+      false => Ok(Self::new(campus, day)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  #[case(Source::today("4korp"), "4korp/today")]
+  #[case(Source::next("1korp"), "1korp/next")]
+  fn roundtrip(#[case] source: Source, #[case] name: &str) {
+    assert_eq!(source.to_string(), name);
+    assert_eq!(name.parse::<Source>(), Ok(source));
+  }
+
+  #[rstest]
+  #[case("today")]
+  #[case("/today")]
+  #[case("4korp/yesterday")]
+  fn invalid(#[case] name: &str) {
+    assert!(name.parse::<Source>().is_err())
+  }
+}
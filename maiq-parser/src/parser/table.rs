@@ -11,20 +11,203 @@ macro_rules! impl_parse_exact {
       let dom = tl::parse(html, ParserOptions::default()).ok()?;
       let parser = dom.parser();
       let table = dom.query_selector("table").and_then(|mut table| table.$select())?;
-      let values = parse_table(table.get(parser).unwrap().inner_html(parser))?;
-      if values.iter().all(|col| col.is_empty()) {
+      let table = parse_table(table.get(parser).unwrap().inner_html(parser))?;
+      if table.is_empty() {
         return None;
       }
-      Some(Table { rows: values })
+      Some(table)
     }
   };
 }
 
+/// Больше браузеры не растягивают, см. спецификацию html
+const MAX_COLSPAN: usize = 1000;
+const MAX_ROWSPAN: usize = 65534;
+
 // todo: сделать parse_first_table и parse_last_table методами Table
 
-#[derive(Debug, PartialEq)]
+/// Таблица как она записана в html: строки `<tr>` с ячейками по порядку, включая пустые ячейки и пустые строки.
+/// Где ячейка стоит на самом деле с учётом `rowspan` и `colspan`, показывает [`Table::grid`]
+#[derive(Clone, Default, Debug, PartialEq)]
 pub struct Table {
-  pub rows: Vec<Vec<String>>,
+  /// Текст `<caption>`, если он есть
+  pub caption: Option<String>,
+  pub rows: Vec<Vec<Cell>>,
+}
+
+/// `<td>` или `<th>`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+  /// Текст после [`normalize_text`], может быть пустым
+  pub text: String,
+  /// `0` - до конца таблицы
+  pub rowspan: usize,
+  pub colspan: usize,
+  pub header: bool,
+}
+
+/// Логическая сетка таблицы: объединённая ячейка занимает все накрытые ей позиции, а позиции, которые не накрыла
+/// ни одна ячейка (короткие строки), пустые
+#[derive(Debug)]
+pub struct Grid<'a> {
+  slots: Vec<Vec<Option<Slot<'a>>>>,
+  width: usize,
+}
+
+/// Позиция сетки и ячейка, которая её накрывает
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot<'a> {
+  pub cell: &'a Cell,
+  /// Строка и столбец, где ячейка начинается
+  pub origin: (usize, usize),
+  /// Позиция накрыта `rowspan` или `colspan` ячейки, начавшейся раньше
+  pub spanned: bool,
+}
+
+impl Cell {
+  pub fn new<S: Into<String>>(text: S) -> Self {
+    Self { text: text.into(), rowspan: 1, colspan: 1, header: false }
+  }
+
+  pub fn with_span(self, rowspan: usize, colspan: usize) -> Self {
+    Self { rowspan, colspan, ..self }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.text.is_empty()
+  }
+}
+
+impl Table {
+  /// Таблица из текстов ячеек без объединений
+  pub fn from_text_rows<S: AsRef<str>, R: IntoIterator<Item = S>, I: IntoIterator<Item = R>>(rows: I) -> Self {
+    let rows = rows
+      .into_iter()
+      .map(|row| row.into_iter().map(|text| Cell::new(text.as_ref())).collect())
+      .collect();
+    Self { caption: None, rows }
+  }
+
+  /// Во всех ячейках пусто
+  pub fn is_empty(&self) -> bool {
+    self.rows.iter().flatten().all(|cell| cell.is_empty())
+  }
+
+  /// Сколько ячеек с текстом
+  pub fn filled_cells(&self) -> usize {
+    self.rows.iter().flatten().filter(|cell| !cell.is_empty()).count()
+  }
+
+  /// Тексты непустых ячеек по строкам, без пустых строк. Позиции и объединения теряются
+  pub fn text_rows(&self) -> Vec<Vec<String>> {
+    self
+      .rows
+      .iter()
+      .map(|row| {
+        row
+          .iter()
+          .filter(|cell| !cell.is_empty())
+          .map(|cell| cell.text.clone())
+          .collect::<Vec<String>>()
+      })
+      .filter(|row| !row.is_empty())
+      .collect()
+  }
+
+  /// Раскладывает ячейки по сетке так же, как браузер: каждая следующая ячейка строки встаёт в первую позицию,
+  /// не занятую `rowspan` из строк выше. `rowspan` не выходит за последнюю строку таблицы
+  pub fn grid(&self) -> Grid<'_> {
+    let height = self.rows.len();
+    let mut slots: Vec<Vec<Option<Slot>>> = vec![vec![]; height];
+
+    for (row, cells) in self.rows.iter().enumerate() {
+      let mut col = 0;
+      for cell in cells {
+        while slots[row].get(col).is_some_and(|slot| slot.is_some()) {
+          col += 1;
+        }
+
+        let rowspan = match cell.rowspan {
+          0 => height - row,
+          rowspan => rowspan.min(height - row),
+        };
+        for (dr, slots) in slots[row..row + rowspan].iter_mut().enumerate() {
+          for dc in 0..cell.colspan {
+            if slots.len() <= col + dc {
+              slots.resize(col + dc + 1, None);
+            }
+            slots[col + dc] = Some(Slot { cell, origin: (row, col), spanned: dr > 0 || dc > 0 });
+          }
+        }
+        col += cell.colspan;
+      }
+    }
+
+    let width = slots.iter().map(|row| row.len()).max().unwrap_or_default();
+    for row in slots.iter_mut() {
+      row.resize(width, None);
+    }
+    Grid { slots, width }
+  }
+}
+
+impl<'a> Grid<'a> {
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn get(&self, row: usize, col: usize) -> Option<&Slot<'a>> {
+    self.slots.get(row)?.get(col)?.as_ref()
+  }
+
+  /// Текст в позиции. Для объединённой ячейки - её текст в каждой накрытой позиции
+  pub fn text(&self, row: usize, col: usize) -> &'a str {
+    self
+      .get(row, col)
+      .map(|slot| slot.cell.text.as_str())
+      .unwrap_or_default()
+  }
+
+  /// Как [`Grid::text`], но продолжение `colspan` ячейки из столбца левее считается пустым: объединённый заголовок
+  /// или примечание на всю ширину не должны попасть в каждый столбец
+  pub fn column_text(&self, row: usize, col: usize) -> &'a str {
+    match self.get(row, col) {
+      Some(slot) if slot.origin.1 == col => slot.cell.text.as_str(),
+      _ => "",
+    }
+  }
+
+  /// Непустые тексты ячеек, которые начинаются в этой строке, - то же, что строка из [`Table::text_rows`]
+  pub fn own_texts(&self, row: usize) -> Vec<&'a str> {
+    self
+      .row(row)
+      .iter()
+      .enumerate()
+      .filter_map(|(col, slot)| slot.filter(|slot| slot.origin == (row, col)))
+      .map(|slot| slot.cell.text.as_str())
+      .filter(|text| !text.is_empty())
+      .collect()
+  }
+
+  pub fn row(&self, row: usize) -> &[Option<Slot<'a>>] {
+    self.slots.get(row).map(|row| row.as_slice()).unwrap_or_default()
+  }
+
+  /// Тексты строки по столбцам, длиной [`Grid::width`]
+  pub fn row_texts(&self, row: usize) -> Vec<&'a str> {
+    (0..self.width).map(|col| self.text(row, col)).collect()
+  }
+
+  pub fn column(&self, col: usize) -> impl Iterator<Item = Option<&Slot<'a>>> {
+    self
+      .slots
+      .iter()
+      .map(move |row| row.get(col).and_then(|slot| slot.as_ref()))
+  }
 }
 
 pub fn parse_all_tables(html: &str) -> Option<Vec<Table>> {
@@ -34,9 +217,8 @@ pub fn parse_all_tables(html: &str) -> Option<Vec<Table>> {
 
   let values = tables
     .filter_map(|table| parse_table(table.get(parser).unwrap().inner_html(parser)))
-    .map(|rows| Table { rows })
     .collect::<Vec<Table>>();
-  if values.is_empty() || values.iter().all(|table| table.rows.iter().all(|col| col.is_empty())) {
+  if values.is_empty() || values.iter().all(|table| table.is_empty()) {
     return None;
   }
   Some(values)
@@ -45,35 +227,53 @@ pub fn parse_all_tables(html: &str) -> Option<Vec<Table>> {
 impl_parse_exact!(parse_first_table, next);
 impl_parse_exact!(parse_last_table, last);
 
-fn parse_table(html: Cow<str>) -> Option<Vec<Vec<String>>> {
+fn parse_table(html: Cow<str>) -> Option<Table> {
   let dom = tl::parse(&html, ParserOptions::default()).ok()?;
   let parser = dom.parser();
+  let caption = dom
+    .query_selector("caption")
+    .and_then(|mut captions| captions.next())
+    .and_then(|caption| caption.get(parser))
+    .map(|caption| normalize_text(caption.inner_text(parser).trim()))
+    .filter(|caption| !caption.is_empty());
   let trs = dom.query_selector("tr")?;
 
-  let table = trs
-    .map(|tr| tr.get(parser).unwrap())
-    .map(|tr| {
-      tr.children()
-        .unwrap()
+  let rows = trs
+    .filter_map(|tr| tr.get(parser)?.children())
+    .map(|children| {
+      children
         .top()
         .iter()
-        .filter_map(|handle| get_inner_text(parser, handle))
-        .map(normalize_text)
-        .collect::<Vec<String>>()
+        .filter_map(|handle| handle.get(parser)?.as_tag())
+        .filter_map(|tag| parse_cell(parser, tag))
+        .collect::<Vec<Cell>>()
     })
-    .filter(|x| !x.iter().all(|x| x.is_empty()))
-    .collect::<Vec<Vec<String>>>();
+    .collect::<Vec<Vec<Cell>>>();
 
-  Some(table)
+  Some(Table { caption, rows })
 }
 
-fn get_inner_text(parser: &Parser, node: &NodeHandle) -> Option<String> {
-  let res = node.get(parser)?.inner_text(parser);
-  let res = res.trim();
-  match res.len() {
-    0 => None,
-    _ => Some(res.into()),
-  }
+fn parse_cell(parser: &Parser, tag: &HTMLTag) -> Option<Cell> {
+  let header = match tag.name().as_utf8_str().to_lowercase().as_str() {
+    "td" => false,
+    "th" => true,
+    _ => return None,
+  };
+  let span = |name: &str, max: usize| {
+    tag
+      .attributes()
+      .get(name)
+      .flatten()
+      .and_then(|value| value.as_utf8_str().trim().parse::<usize>().ok())
+      .map_or(1, |span| span.min(max))
+  };
+
+  Some(Cell {
+    text: normalize_text(tag.inner_text(parser).trim()),
+    rowspan: span("rowspan", MAX_ROWSPAN),
+    colspan: span("colspan", MAX_COLSPAN).max(1),
+    header,
+  })
 }
 
 const PATTERNS: [&str; 14] = [
@@ -116,10 +316,10 @@ mod tests {
 
   macro_rules! table {
     [$(($($v: literal),*)),*] => {
-      Some(Table { rows: vec![$(vec![$($v.to_string(),)*],)*] })
+      Some(vec![$(vec![$($v.to_string(),)*],)*])
     };
     [$([$(($($v: literal),*)),*]),*] => {
-      Some(vec![$(Table { rows: vec![$(vec![$($v.to_string(),)*],)*] },)*])
+      Some(vec![$(vec![$(vec![$($v.to_string(),)*],)*],)*])
     };
   }
 
@@ -147,15 +347,86 @@ mod tests {
   #[case(SPECIAL_SYMBOL, table![("A", "<")])]
   #[case("<table>Hey</table>", None)]
   #[case("<div>Hi</div>", None)]
-  fn simple(#[case] html: &str, #[case] expected: Option<Table>) {
-    assert_eq!(expected, parse_first_table(html));
+  fn simple(#[case] html: &str, #[case] expected: Option<Vec<Vec<String>>>) {
+    assert_eq!(expected, parse_first_table(html).map(|table| table.text_rows()));
   }
 
   #[rstest]
   #[case(TWO_TABLES, table![[("Header", "Value"), ("A", "B")], [("Header", "Value"), ("A", "B")]])]
   #[case("<div>Hi</div>", None)]
-  fn multiple(#[case] html: &str, #[case] expected: Option<Vec<Table>>) {
-    assert_eq!(expected, parse_all_tables(html));
+  fn multiple(#[case] html: &str, #[case] expected: Option<Vec<Vec<Vec<String>>>>) {
+    let tables = parse_all_tables(html).map(|tables| tables.iter().map(|table| table.text_rows()).collect());
+    assert_eq!(expected, tables);
+  }
+
+  #[rstest]
+  fn caption() {
+    let table = parse_first_table("<table><caption> Изменения\n на 18 октября </caption><tr><td>A</td></tr></table>").unwrap();
+    assert_eq!(table.caption.as_deref(), Some("Изменения на 18 октября"));
+    assert_eq!(parse_first_table(ONE_TABLE_TWO_ROWS).unwrap().caption, None);
+  }
+
+  const MERGED: &str = r#"<table>
+        <tr><th colspan="4">Изменения в расписании на 18 октября</th></tr>
+        <tr><td rowspan="3">Ир3-21</td><td>1</td><td>Математика</td><td>301</td></tr>
+        <tr><td>2</td><td></td><td>&nbsp;</td></tr>
+        <tr><td>3</td><td colspan="2">История</td></tr>
+        <tr><td>С1-22</td><td>1</td></tr>
+    </table>"#;
+
+  #[rstest]
+  fn cells() {
+    let table = parse_first_table(MERGED).unwrap();
+    assert_eq!(table.rows.len(), 5);
+    assert_eq!(table.rows[0][0], Cell { header: true, ..Cell::new("Изменения в расписании на 18 октября").with_span(1, 4) });
+    assert_eq!(table.rows[1][0], Cell::new("Ир3-21").with_span(3, 1));
+    assert_eq!(
+      table.rows[2]
+        .iter()
+        .map(|cell| cell.text.as_str())
+        .collect::<Vec<_>>(),
+      vec!["2", "", ""]
+    );
+    assert_eq!(table.text_rows()[2], vec!["2"]);
+  }
+
+  #[rstest]
+  fn grid() {
+    let table = parse_first_table(MERGED).unwrap();
+    let grid = table.grid();
+    assert_eq!((grid.height(), grid.width()), (5, 4));
+    assert_eq!(grid.row_texts(0), vec!["Изменения в расписании на 18 октября"; 4]);
+    assert_eq!(grid.row_texts(2), vec!["Ир3-21", "2", "", ""]);
+    assert_eq!(grid.row_texts(3), vec!["Ир3-21", "3", "История", "История"]);
+    assert_eq!(grid.row_texts(4), vec!["С1-22", "1", "", ""]);
+    assert!(grid.get(4, 2).is_none());
+
+    assert_eq!(grid.column_text(3, 3), "");
+    assert_eq!(grid.column_text(3, 0), "Ир3-21");
+    assert_eq!(grid.own_texts(3), vec!["3", "История"]);
+    assert_eq!(grid.own_texts(2), vec!["2"]);
+
+    let slot = grid.get(3, 0).unwrap();
+    assert_eq!((slot.origin, slot.spanned), ((1, 0), true));
+    assert!(!grid.get(1, 0).unwrap().spanned);
+    assert_eq!(
+      grid
+        .column(1)
+        .map(|slot| slot.map(|slot| slot.cell.text.as_str()))
+        .collect::<Vec<_>>(),
+      vec![Some("Изменения в расписании на 18 октября"), Some("1"), Some("2"), Some("3"), Some("1")]
+    );
+  }
+
+  #[rstest]
+  #[case(r#"<tr><td rowspan="0">A</td><td>B</td></tr><tr><td>C</td></tr>"#, vec![vec!["A", "B"], vec!["A", "C"]])]
+  #[case(r#"<tr><td rowspan="5">A</td><td>B</td></tr><tr><td>C</td></tr>"#, vec![vec!["A", "B"], vec!["A", "C"]])]
+  #[case(r#"<tr><td colspan="0">A</td><td>B</td></tr>"#, vec![vec!["A", "B"]])]
+  #[case(r#"<tr><td colspan="x">A</td><td>B</td></tr>"#, vec![vec!["A", "B"]])]
+  fn spans(#[case] rows: &str, #[case] expected: Vec<Vec<&str>>) {
+    let table = parse_first_table(&format!("<table>{}</table>", rows)).unwrap();
+    let grid = table.grid();
+    assert_eq!((0..grid.height()).map(|row| grid.row_texts(row)).collect::<Vec<_>>(), expected);
   }
 
   #[rstest]
@@ -1,6 +1,12 @@
+pub use crate::parser::control::*;
+pub use crate::parser::event::*;
 pub use crate::parser::impls::*;
 pub use crate::parser::repeating::*;
+pub use crate::parser::report::*;
+pub use crate::parser::schedule::*;
+pub use crate::parser::source::*;
 pub use crate::parser::SnapshotParserAgent;
 pub use crate::parser::SnapshotParserBuilder;
 
+pub use crate::diff::*;
 pub use crate::snapshot::*;
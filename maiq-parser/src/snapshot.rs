@@ -1,21 +1,22 @@
 use serde::Deserialize;
 use serde::Serialize;
 
-use std::collections::hash_map::DefaultHasher;
-use std::hash::*;
-
-use crate::utils::time::*;
+use crate::diff::SnapshotDiff;
+use crate::parser::source::Source;
+use crate::utils::hash::StableHasher;
+use maiq_time::*;
 
 use std::slice::Iter;
 
-// todo: избавиться от Box<str>
-pub type Changes = Vec<String>;
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Snapshot {
   #[serde(default)]
   id: u64,
   date: DateTime,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  source: Option<Source>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  agent: Option<Box<str>>,
   groups: Vec<Group>,
 }
 
@@ -27,7 +28,7 @@ pub struct Group {
   lectures: Vec<Lecture>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Lecture {
   #[serde(default)]
   id: u64,
@@ -44,9 +45,16 @@ pub trait Id {
 }
 
 impl Id for Snapshot {
+  /// Дата (число дней с начала эры), корпус источника (если задан), количество групп и их идентификаторы. Страница
+  /// источника не учитывается: страница "на завтра", ставшая страницей "на сегодня", - тот же снапшот
   fn compute_id(&mut self) {
-    let mut hash = DefaultHasher::default();
-    self.groups().for_each(|group| group.id().hash(&mut hash));
+    let mut hash = StableHasher::new();
+    hash.write_i64(self.date.date_naive().num_days_from_ce() as i64);
+    if let Some(source) = self.source.as_ref() {
+      hash.write_str(source.campus());
+    }
+    hash.write_u64(self.groups.len() as u64);
+    self.groups().for_each(|group| hash.write_u64(group.id()));
     self.id = hash.finish();
   }
 
@@ -56,9 +64,12 @@ impl Id for Snapshot {
 }
 
 impl Id for Group {
+  /// Название группы, количество пар и их идентификаторы
   fn compute_id(&mut self) {
-    let mut hash = DefaultHasher::default();
-    self.lectures().for_each(|lecture| lecture.id().hash(&mut hash));
+    let mut hash = StableHasher::new();
+    hash.write_str(self.name());
+    hash.write_u64(self.lectures.len() as u64);
+    self.lectures().for_each(|lecture| hash.write_u64(lecture.id()));
     self.id = hash.finish();
   }
 
@@ -68,14 +79,15 @@ impl Id for Group {
 }
 
 impl Id for Lecture {
+  /// Номер, название, подгруппа, кабинет и преподаватель - именно в таком порядке
   fn compute_id(&mut self) {
-    let mut hash = DefaultHasher::default();
+    let mut hash = StableHasher::new();
 
-    self.order().unwrap_or_default().hash(&mut hash);
-    self.name().hash(&mut hash);
-    self.subgroup().unwrap_or_default().hash(&mut hash);
-    self.classroom().unwrap_or_default().hash(&mut hash);
-    self.teacher().unwrap_or_default().hash(&mut hash);
+    hash.write_opt_str(self.order());
+    hash.write_str(self.name());
+    hash.write_opt_str(self.subgroup());
+    hash.write_opt_str(self.classroom());
+    hash.write_opt_str(self.teacher());
 
     self.id = hash.finish();
   }
@@ -87,14 +99,36 @@ impl Id for Lecture {
 
 impl Snapshot {
   pub fn new(date: DateTime, groups: Vec<Group>) -> Self {
-    Self { id: 0, date, groups }.sort_groups()
+    Self { id: 0, date, source: None, agent: None, groups }.sort_groups()
+  }
+
+  /// Помечает снапшот страницей, с которой он получен
+  pub fn with_source(mut self, source: Source) -> Self {
+    self.source = Some(source);
+    self.compute_id();
+    self
+  }
+
+  pub fn source(&self) -> Option<&Source> {
+    self.source.as_ref()
+  }
+
+  /// Помечает снапшот агентом, который его разобрал. На идентификатор не влияет: тот же снапшот от другого агента -
+  /// не изменение
+  pub fn with_agent<S: Into<Box<str>>>(self, agent: S) -> Self {
+    Self { agent: Some(agent.into()), ..self }
+  }
+
+  /// Имя агента, см. [`crate::parser::SnapshotParserAgent::NAME`]
+  pub fn agent(&self) -> Option<&str> {
+    self.agent.as_deref()
   }
 
   pub fn group(&self, name: &str) -> Option<&Group> {
     self.groups.iter().find(|group| *group.name == *name)
   }
 
-  pub fn groups(&self) -> Iter<Group> {
+  pub fn groups(&self) -> Iter<'_, Group> {
     self.groups.iter()
   }
 
@@ -102,6 +136,10 @@ impl Snapshot {
     self.date
   }
 
+  pub fn diff(&self, next: &Snapshot) -> SnapshotDiff {
+    SnapshotDiff::new(Some(self), next)
+  }
+
   fn sort_groups(mut self) -> Self {
     self.groups.iter_mut().for_each(|g| {
       g.lectures.sort_by_key(|g| g.subgroup.clone());
@@ -123,7 +161,7 @@ impl Group {
     &self.name
   }
 
-  pub fn lectures(&self) -> Iter<Lecture> {
+  pub fn lectures(&self) -> Iter<'_, Lecture> {
     self.lectures.iter()
   }
 
@@ -171,38 +209,6 @@ impl Lecture {
   }
 }
 
-pub trait SnapshotChanges {
-  fn changes(&self, rhs: Self, placeholder: &[&str]) -> Vec<String>;
-}
-
-impl SnapshotChanges for Option<&Snapshot> {
-  fn changes(&self, rhs: Self, defs: &[&str]) -> Vec<String> {
-    let (lhs, rhs) = match (self, rhs) {
-      (Some(lhs), Some(rhs)) if lhs.id() == rhs.id() => return vec![],
-      (Some(lhs), Some(rhs)) => (lhs, rhs),
-      (Some(_), None) => return vec![],
-      (None, Some(_)) => return defs.iter().map(|x| x.to_string()).collect(),
-      (None, None) => return vec![],
-    };
-
-    let is_updated = |name: &String| -> bool {
-      let lhs = lhs.group(name);
-      let rhs = rhs.group(name);
-
-      match (lhs, rhs) {
-        (None, Some(_)) => true,
-        (Some(_), None) => true,
-        (Some(lhs), Some(rhs)) if lhs.id() != rhs.id() => true,
-        _ => false,
-      }
-    };
-
-    let mut changes = defs.iter().map(|x| x.to_string()).collect::<Vec<_>>();
-    changes.retain(is_updated);
-    changes
-  }
-}
-
 #[cfg(test)]
 mod tests {
   use crate::snapshot::*;
@@ -223,6 +229,40 @@ mod tests {
 
   #[rstest]
   fn diff_lectures(#[from(snapshot_1)] s1: Snapshot, #[from(snapshot_2)] s2: Snapshot) {
-    assert_eq!(vec!["Group1".to_string()], Some(&s1).changes(Some(&s2), &["Group1"]))
+    let diff = s1.diff(&s2);
+    assert_eq!(vec!["Group1"], diff.groups().map(|g| g.name()).collect::<Vec<_>>())
+  }
+
+  fn date(day: u32) -> DateTime {
+    FixedOffset::east_opt(3600 * 3)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, day, 0, 0, 0)
+      .unwrap()
+  }
+
+  #[rstest]
+  fn stable_ids(#[from(snapshot_1)] s1: Snapshot) {
+    let snapshot = Snapshot::new(date(18), s1.groups.clone());
+    let group = snapshot.group("Group1").unwrap();
+    assert_eq!(group.lectures().next().unwrap().id(), 12294915034538559582);
+    assert_eq!(group.id(), 16023379098519230985);
+    assert_eq!(snapshot.id(), 17712105463564165811);
+  }
+
+  #[rstest]
+  fn snapshot_id_covers_date(#[from(snapshot_1)] s1: Snapshot) {
+    let lhs = Snapshot::new(date(18), s1.groups.clone());
+    let rhs = Snapshot::new(date(19), s1.groups.clone());
+    assert_ne!(lhs.id(), rhs.id());
+  }
+
+  #[rstest]
+  fn snapshot_id_covers_campus(#[from(snapshot_1)] s1: Snapshot) {
+    let lhs = Snapshot::new(date(18), s1.groups.clone()).with_source(Source::today("4korp"));
+    let rhs = Snapshot::new(date(18), s1.groups.clone()).with_source(Source::today("1korp"));
+    let next = Snapshot::new(date(18), s1.groups.clone()).with_source(Source::next("4korp"));
+    assert_ne!(lhs.id(), rhs.id());
+    assert_eq!(lhs.id(), next.id());
+    assert_eq!(lhs.source(), Some(&Source::today("4korp")));
   }
 }
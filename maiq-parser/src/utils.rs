@@ -1,29 +1,87 @@
-pub mod time {
-  pub use chrono::prelude::*;
+pub mod hash {
+  //! Детерминированный хеш для идентификаторов снапшотов, групп и пар.
+  //!
+  //! Алгоритм - 64-битный FNV-1a (offset basis `0xcbf29ce484222325`, prime `0x100000001b3`) над байтами:
+  //! - числа пишутся как 8 байт little-endian;
+  //! - строки - длина в байтах (как число), затем байты UTF-8;
+  //! - `Option<&str>` - байт `0` для `None`, либо байт `1` и строка.
+  //!
+  //! В отличие от `DefaultHasher`, результат не зависит от версии Rust и платформы,
+  //! поэтому идентификаторы можно хранить в базе и сравнивать между деплоями.
 
-  pub type DateTime = chrono::DateTime<FixedOffset>;
+  const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+  const PRIME: u64 = 0x100000001b3;
 
-  const TIME_OFFSET: i32 = 3600 * 3;
+  #[derive(Clone, Copy, Debug)]
+  pub struct StableHasher(u64);
 
-  pub trait DateTimeExt {
-    fn now() -> Self;
-    fn now_date() -> Self;
+  impl Default for StableHasher {
+    fn default() -> Self {
+      Self(OFFSET_BASIS)
+    }
   }
 
-  impl DateTimeExt for DateTime {
-    fn now() -> Self {
-      let offset = FixedOffset::east_opt(TIME_OFFSET).unwrap();
-      Utc::now().with_timezone(&offset).with_nanosecond(0).unwrap()
+  impl StableHasher {
+    pub fn new() -> Self {
+      Self::default()
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+      for byte in bytes {
+        self.0 ^= *byte as u64;
+        self.0 = self.0.wrapping_mul(PRIME);
+      }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+      self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+      self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+      self.write_u64(value.len() as u64);
+      self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_opt_str(&mut self, value: Option<&str>) {
+      match value {
+        Some(value) => {
+          self.write_bytes(&[1]);
+          self.write_str(value);
+        }
+        None => self.write_bytes(&[0]),
+      }
+    }
+
+    pub fn finish(&self) -> u64 {
+      self.0
+    }
+  }
+
+  #[cfg(test)]
+  mod tests {
+    use super::*;
+
+    #[rstest]
+    #[case(b"", 0xcbf29ce484222325)]
+    #[case(b"a", 0xaf63dc4c8601ec8c)]
+    #[case(b"foobar", 0x85944171f73967e8)]
+    fn fnv1a(#[case] input: &[u8], #[case] expected: u64) {
+      let mut hasher = StableHasher::new();
+      hasher.write_bytes(input);
+      assert_eq!(hasher.finish(), expected);
     }
 
-    fn now_date() -> Self {
-      DateTime::now()
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
+    #[rstest]
+    fn option_is_not_empty_str() {
+      let mut none = StableHasher::new();
+      none.write_opt_str(None);
+      let mut empty = StableHasher::new();
+      empty.write_opt_str(Some(""));
+      assert_ne!(none.finish(), empty.finish());
     }
   }
 }
@@ -0,0 +1,113 @@
+//! Прогоняет сохранённые страницы из `tests/fixtures/<parser>/*.htm` через парсер и сравнивает снапшот и отчёт
+//! о пропущенных строках с лежащими рядом `*.json`. Чтобы осознанно перезаписать эталоны, запустите тесты с `UPDATE_GOLDEN=1`.
+//!
+//! Страницы можно класть как есть: если файл не в UTF-8, он читается как `windows-1251`. Таблица выбирается
+//! [`TableSelection::Largest`], как в боте.
+//! Список известных парсеру групп лежит в `groups.txt` в той же директории.
+//!
+//! Страницу, которую парсер пока разбирает неправильно, кладут с правильным эталоном, написанным руками, и файлом
+//! `*.xfail` с причиной рядом. Такой тест обязан расходиться с эталоном, `UPDATE_GOLDEN` его не перезаписывает, а
+//! когда парсер починят, тест попросит убрать `*.xfail`.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+use maiq_parser_next::parser::read_group_names;
+use maiq_parser_next::parser::selection::TableSelection;
+use maiq_parser_next::prelude::*;
+use maiq_time::DateTime;
+
+fn fallback_date() -> DateTime {
+  FixedOffset::east_opt(3600 * 3)
+    .unwrap()
+    .with_ymd_and_hms(2023, 10, 16, 9, 0, 0)
+    .unwrap()
+}
+
+fn read_page(path: &Path) -> String {
+  let raw = fs::read(path).expect("unable to read fixture");
+  match String::from_utf8(raw) {
+    Ok(html) => html,
+    Err(err) => encoding_rs::WINDOWS_1251.decode(err.as_bytes()).0.into_owned(),
+  }
+}
+
+fn fixtures(parser: &str) -> Vec<PathBuf> {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/fixtures")
+    .join(parser);
+  let mut pages = fs::read_dir(dir)
+    .expect("unable to read fixtures dir")
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().is_some_and(|ext| ext == "htm" || ext == "html"))
+    .collect::<Vec<PathBuf>>();
+  pages.sort();
+  pages
+}
+
+fn fixture_groups(parser: &str) -> Vec<Box<str>> {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("tests/fixtures")
+    .join(parser)
+    .join("groups.txt");
+  read_group_names(path).expect("unable to read fixture groups")
+}
+
+fn run_golden<F: Fn(&str) -> serde_json::Value>(parser: &str, parse: F) {
+  let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| !v.is_empty() && v != "0");
+  let mut failed = vec![];
+
+  for page in fixtures(parser) {
+    let actual = parse(&read_page(&page));
+    let golden = page.with_extension("json");
+    let xfail = fs::read_to_string(page.with_extension("xfail")).ok();
+
+    if update && xfail.is_none() {
+      fs::write(&golden, serde_json::to_string_pretty(&actual).unwrap() + "\n").expect("unable to write golden file");
+      continue;
+    }
+
+    let expected = match fs::read_to_string(&golden) {
+      Ok(expected) => serde_json::from_str::<serde_json::Value>(&expected).expect("invalid golden file"),
+      Err(_) => {
+        failed.push(format!("{}: no golden file; run with UPDATE_GOLDEN=1", golden.display()));
+        continue;
+      }
+    };
+
+    if let Some(reason) = xfail {
+      if expected == actual {
+        failed.push(format!("{}: expected to fail ({}), but matches golden file; remove .xfail", page.display(), reason.trim()));
+      }
+      continue;
+    }
+
+    if expected != actual {
+      failed.push(format!(
+        "{}: snapshot differs from golden file\nexpected: {}\nactual: {}",
+        page.display(),
+        serde_json::to_string_pretty(&expected).unwrap(),
+        serde_json::to_string_pretty(&actual).unwrap()
+      ));
+    }
+  }
+
+  assert!(failed.is_empty(), "{}", failed.join("\n\n"));
+}
+
+#[test]
+fn parser4() {
+  let groups = fixture_groups("parser4");
+  run_golden("parser4", |html| {
+    let (table, choice) = TableSelection::Largest
+      .select_html(html)
+      .expect("no table in fixture");
+    let (snapshot, report) = SnapshotParser4::new(fallback_date())
+      .with_groups(groups.iter())
+      .parse(table);
+    let report = report.with_table(choice);
+    serde_json::json!({ "snapshot": snapshot, "report": report })
+  });
+}
@@ -0,0 +1,95 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use chrono::prelude::*;
+
+use crate::timezone;
+use crate::DateTime;
+
+/// Откуда берётся текущее время. Всё, что зависит от "сейчас" (окна опроса, "сегодня" и "завтра", дата таблицы без
+/// года), спрашивает его у часов, а не у системы, поэтому в тестах время можно зафиксировать через [`FixedClock`]
+pub trait Clock: Send + Sync + Debug {
+  fn now_utc(&self) -> chrono::DateTime<Utc>;
+
+  /// Текущий момент в часовом поясе из [`timezone`], с точностью до секунды
+  fn now(&self) -> DateTime {
+    timezone().from_utc(&self.now_utc()).with_nanosecond(0).unwrap()
+  }
+
+  fn today(&self) -> NaiveDate {
+    self.now().date_naive()
+  }
+
+  /// Местная полночь сегодняшнего дня
+  fn now_date(&self) -> DateTime {
+    timezone().midnight(self.today())
+  }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Системные часы
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now_utc(&self) -> chrono::DateTime<Utc> {
+    Utc::now()
+  }
+}
+
+/// Часы, которые стоят, пока их не переведут. Клоны показывают одно и то же время
+#[derive(Clone, Debug)]
+pub struct FixedClock(Arc<Mutex<chrono::DateTime<Utc>>>);
+
+impl FixedClock {
+  pub fn new<Z: TimeZone>(at: &chrono::DateTime<Z>) -> Self {
+    Self(Arc::new(Mutex::new(at.with_timezone(&Utc))))
+  }
+
+  pub fn set<Z: TimeZone>(&self, at: &chrono::DateTime<Z>) {
+    *self.0.lock().unwrap() = at.with_timezone(&Utc);
+  }
+
+  pub fn advance(&self, by: chrono::Duration) {
+    *self.0.lock().unwrap() += by;
+  }
+}
+
+impl Clock for FixedClock {
+  fn now_utc(&self) -> chrono::DateTime<Utc> {
+    *self.0.lock().unwrap()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[rstest]
+  fn fixed() {
+    let at = FixedOffset::east_opt(3 * 3600)
+      .unwrap()
+      .with_ymd_and_hms(2023, 10, 17, 23, 30, 15)
+      .unwrap();
+    let clock = FixedClock::new(&at);
+    let shared: SharedClock = Arc::new(clock.clone());
+    assert_eq!(shared.now(), at);
+    assert_eq!(shared.today(), NaiveDate::from_ymd_opt(2023, 10, 17).unwrap());
+
+    clock.advance(chrono::Duration::hours(1));
+    assert_eq!(shared.today(), NaiveDate::from_ymd_opt(2023, 10, 18).unwrap());
+    assert_eq!(
+      shared.now_date(),
+      at.with_day(18)
+        .unwrap()
+        .with_hour(0)
+        .unwrap()
+        .with_minute(0)
+        .unwrap()
+        .with_second(0)
+        .unwrap()
+    );
+  }
+}
@@ -0,0 +1,44 @@
+//! Время для всех крейтов: часовой пояс колледжа, [`DateTime`] в нём и [`Clock`], который в тестах подменяется на
+//! [`FixedClock`]
+
+mod clock;
+mod timezone;
+
+pub use chrono::prelude::*;
+
+pub use clock::*;
+pub use timezone::*;
+
+pub type DateTime = chrono::DateTime<FixedOffset>;
+
+#[cfg(test)]
+#[macro_use]
+extern crate rstest;
+
+pub trait DateTimeExt {
+  /// Текущий момент по [`SystemClock`]
+  fn now() -> Self;
+  /// Начало сегодняшнего дня по [`SystemClock`]
+  fn now_date() -> Self;
+  /// sqlite хранит `datetime()` в UTC
+  fn from_naive(datetime: NaiveDateTime) -> Self;
+  fn from_naive_date(date: NaiveDate) -> Self;
+}
+
+impl DateTimeExt for DateTime {
+  fn now() -> Self {
+    SystemClock.now()
+  }
+
+  fn now_date() -> Self {
+    SystemClock.now_date()
+  }
+
+  fn from_naive(datetime: NaiveDateTime) -> Self {
+    timezone().from_naive_utc(&datetime)
+  }
+
+  fn from_naive_date(date: NaiveDate) -> Self {
+    timezone().midnight(date)
+  }
+}
@@ -0,0 +1,166 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use chrono::prelude::*;
+use chrono::LocalResult;
+pub use chrono_tz::Tz;
+
+use crate::DateTime;
+
+/// Смещение по умолчанию, UTC+3
+const DEFAULT_OFFSET: i32 = 3600 * 3;
+
+static TIMEZONE: RwLock<Option<Timezone>> = RwLock::new(None);
+
+/// Часовой пояс колледжа: IANA-имя (`Europe/Moscow`) или фиксированное смещение (`+03:00`, `UTC+3`).
+/// В нём считаются даты таблиц, окна опроса, "сегодня" и "завтра" и время в базе
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timezone {
+  Offset(FixedOffset),
+  Named(Tz),
+}
+
+/// Задаёт часовой пояс для всего процесса. Вызывается один раз при старте, до запуска парсера
+pub fn set_timezone(timezone: Timezone) {
+  *TIMEZONE.write().unwrap() = Some(timezone);
+}
+
+pub fn timezone() -> Timezone {
+  TIMEZONE.read().unwrap().unwrap_or_default()
+}
+
+impl Default for Timezone {
+  fn default() -> Self {
+    Self::Offset(FixedOffset::east_opt(DEFAULT_OFFSET).unwrap())
+  }
+}
+
+impl Timezone {
+  /// Смещение, действующее в момент `utc`
+  pub fn offset_at(&self, utc: &NaiveDateTime) -> FixedOffset {
+    match self {
+      Timezone::Offset(offset) => *offset,
+      Timezone::Named(tz) => tz.offset_from_utc_datetime(utc).fix(),
+    }
+  }
+
+  pub fn from_utc<Z: TimeZone>(&self, datetime: &chrono::DateTime<Z>) -> DateTime {
+    self.from_naive_utc(&datetime.naive_utc())
+  }
+
+  pub fn from_naive_utc(&self, utc: &NaiveDateTime) -> DateTime {
+    DateTime::from_naive_utc_and_offset(*utc, self.offset_at(utc))
+  }
+
+  /// Начало дня по местному времени. Если полночь попала на перевод часов - первый существующий момент после неё
+  pub fn midnight(&self, date: NaiveDate) -> DateTime {
+    let local = date.and_time(NaiveTime::MIN);
+    match self {
+      Timezone::Offset(offset) => offset.from_local_datetime(&local).unwrap(),
+      Timezone::Named(tz) => match tz.from_local_datetime(&local) {
+        LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => self.from_utc(&datetime),
+        LocalResult::None => self.from_naive_utc(&(local - tz.offset_from_utc_datetime(&local).fix())),
+      },
+    }
+  }
+}
+
+impl FromStr for Timezone {
+  type Err = String;
+
+  /// `Europe/Moscow`, `+03:00`, `+3`, `UTC+3`, `UTC`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    if let Ok(tz) = s.parse::<Tz>() {
+      return Ok(Self::Named(tz));
+    }
+
+    let offset = s.strip_prefix("UTC").or_else(|| s.strip_prefix("GMT")).unwrap_or(s);
+    if offset.is_empty() {
+      return Ok(Self::Offset(FixedOffset::east_opt(0).unwrap()));
+    }
+
+    let invalid = || format!("invalid timezone `{}`", s);
+    let (sign, offset) = match offset.split_at(1) {
+      ("+", rest) => (1, rest),
+      ("-", rest) => (-1, rest),
+      _ => return Err(invalid()),
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let hours = hours.parse::<i32>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<i32>().map_err(|_| invalid())?;
+    if minutes >= 60 {
+      return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+      .map(Self::Offset)
+      .ok_or_else(invalid)
+  }
+}
+
+impl Display for Timezone {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Timezone::Offset(offset) => write!(f, "{}", offset),
+      Timezone::Named(tz) => write!(f, "{}", tz.name()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn utc(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2023, 10, day)
+      .unwrap()
+      .and_hms_opt(hour, minute, 0)
+      .unwrap()
+  }
+
+  #[rstest]
+  #[case("Europe/Moscow", Timezone::Named(Tz::Europe__Moscow))]
+  #[case("+03:00", Timezone::Offset(FixedOffset::east_opt(3 * 3600).unwrap()))]
+  #[case("UTC+3", Timezone::Offset(FixedOffset::east_opt(3 * 3600).unwrap()))]
+  #[case("-04:30", Timezone::Offset(FixedOffset::west_opt(4 * 3600 + 1800).unwrap()))]
+  #[case("UTC", Timezone::Named(Tz::UTC))]
+  fn parse(#[case] raw: &str, #[case] expected: Timezone) {
+    assert_eq!(raw.parse::<Timezone>(), Ok(expected));
+  }
+
+  #[rstest]
+  #[case("Europe/Nowhere")]
+  #[case("+3:75")]
+  #[case("3")]
+  fn invalid(#[case] raw: &str) {
+    assert!(raw.parse::<Timezone>().is_err());
+  }
+
+  #[rstest]
+  #[case("+03:00", utc(17, 20, 59), 17)]
+  #[case("+03:00", utc(17, 21, 0), 18)]
+  #[case("Europe/Moscow", utc(17, 21, 0), 18)]
+  #[case("Europe/Berlin", utc(17, 21, 59), 17)]
+  #[case("Europe/Berlin", utc(17, 22, 0), 18)]
+  #[case("Europe/Berlin", utc(30, 22, 59), 30)]
+  #[case("Europe/Berlin", utc(30, 23, 0), 31)]
+  fn day_boundary(#[case] tz: &str, #[case] utc: NaiveDateTime, #[case] day: u32) {
+    let local = tz.parse::<Timezone>().unwrap().from_naive_utc(&utc);
+    assert_eq!(local.day(), day);
+    assert_eq!(local.naive_utc(), utc);
+  }
+
+  #[rstest]
+  #[case("Europe/Berlin", 28, 2)]
+  #[case("Europe/Berlin", 30, 1)]
+  #[case("+03:00", 30, 3)]
+  fn midnight(#[case] tz: &str, #[case] day: u32, #[case] offset_hours: i32) {
+    let midnight = tz
+      .parse::<Timezone>()
+      .unwrap()
+      .midnight(NaiveDate::from_ymd_opt(2023, 10, day).unwrap());
+    assert_eq!((midnight.day(), midnight.hour()), (day, 0));
+    assert_eq!(midnight.offset().local_minus_utc(), offset_hours * 3600);
+  }
+}